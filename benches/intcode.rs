@@ -0,0 +1,72 @@
+use adventofcode2019::intcode::assemble::assemble;
+use adventofcode2019::intcode::compile::Compiled;
+use adventofcode2019::intcode::Intcode;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+/// Sums the numbers from 1 to its input.
+const SUM: &str = "
+        in [n]
+loop:   add [sum], [n], [sum]
+        add [n], #-1, [n]
+        lt #0, [n], [flag]
+        jt [flag], #loop
+        out [sum]
+        hlt
+n:      data 0
+sum:    data 0
+flag:   data 0
+";
+
+fn run(program: &Intcode, cached: bool, input: i64) -> Vec<i64> {
+    let mut machine = program.clone();
+    if cached {
+        machine.enable_decode_cache();
+    }
+
+    machine.run(vec![input]).unwrap()
+}
+
+fn engines(c: &mut Criterion) {
+    let sum = Intcode::new(assemble(SUM).unwrap());
+    let diagnostics = Intcode::from_file("inputs/day05.txt").unwrap();
+
+    let compiled_sum = Compiled::new(&sum);
+    let compiled_diagnostics = Compiled::new(&diagnostics);
+
+    let mut group = c.benchmark_group("sum");
+    group.bench_function("interpreter", |b| {
+        b.iter(|| run(&sum, false, black_box(100_000)))
+    });
+    group.bench_function("decode cache", |b| {
+        b.iter(|| run(&sum, true, black_box(100_000)))
+    });
+    group.bench_function("compiled", |b| {
+        b.iter(|| {
+            compiled_sum
+                .machine()
+                .run(vec![black_box(100_000)])
+                .unwrap()
+        })
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("day05");
+    group.bench_function("interpreter", |b| {
+        b.iter(|| run(&diagnostics, false, black_box(5)))
+    });
+    group.bench_function("decode cache", |b| {
+        b.iter(|| run(&diagnostics, true, black_box(5)))
+    });
+    group.bench_function("compiled", |b| {
+        b.iter(|| {
+            compiled_diagnostics
+                .machine()
+                .run(vec![black_box(5)])
+                .unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, engines);
+criterion_main!(benches);
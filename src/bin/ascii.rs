@@ -0,0 +1,38 @@
+extern crate adventofcode2019;
+use adventofcode2019::intcode;
+use adventofcode2019::intcode::ascii::Terminal;
+use std::env;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+
+fn main() -> Result<(), intcode::IntcodeError> {
+    let mut args = env::args().skip(1);
+    let filename = args
+        .next()
+        .expect("Usage: ascii <program file> [script file]");
+    let mut terminal = Terminal::new(intcode::Intcode::from_file(&filename)?);
+
+    if let Some(script) = args.next() {
+        print!("{}", terminal.run_script(&fs::read_to_string(script)?)?);
+        return Ok(());
+    }
+
+    print!("{}", terminal.read()?);
+    let stdin = io::stdin();
+    while !terminal.is_halted() {
+        io::stdout().flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+
+        print!(
+            "{}",
+            terminal.send(line.trim_end_matches(&['\r', '\n'][..]))?
+        );
+    }
+
+    Ok(())
+}
@@ -11,11 +11,7 @@ fn load_input(filename: &str) -> Result<Vec<u32>, Error> {
 
 fn fuel_for_module(mass: u32) -> u32 {
     let third = mass / 3;
-    if third < 2 {
-        0
-    } else {
-        third - 2
-    }
+    third.saturating_sub(2)
 }
 
 fn recursive_fuel_for_module(mass: u32) -> u32 {
@@ -26,11 +22,11 @@ fn recursive_fuel_for_module(mass: u32) -> u32 {
     fuel
 }
 
-fn fuel_for_modules(masses: &Vec<u32>) -> u32 {
+fn fuel_for_modules(masses: &[u32]) -> u32 {
     masses.iter().map(|m| fuel_for_module(*m)).sum()
 }
 
-fn recursive_fuel_for_modules(masses: &Vec<u32>) -> u32 {
+fn recursive_fuel_for_modules(masses: &[u32]) -> u32 {
     masses.iter().map(|m| recursive_fuel_for_module(*m)).sum()
 }
 
@@ -84,12 +80,12 @@ mod tests {
 
     #[test]
     fn collect_fuel_requirements() {
-        assert_eq!(658, fuel_for_modules(&vec![12, 14, 1_969]));
+        assert_eq!(658, fuel_for_modules(&[12, 14, 1_969]));
     }
 
     #[test]
     fn collect_recursive_fuel_requirements() {
-        assert_eq!(968, recursive_fuel_for_modules(&vec![14, 1_969]));
+        assert_eq!(968, recursive_fuel_for_modules(&[14, 1_969]));
     }
 
     #[test]
@@ -1,197 +1,107 @@
-#[derive(Debug, PartialEq)]
-enum Opcode {
-    Add,
-    Multiply,
-    Halt,
+extern crate adventofcode2019;
+use adventofcode2019::intcode::isa::InstructionSet;
+use adventofcode2019::intcode::search::{Search, SearchError};
+use adventofcode2019::intcode::{Intcode, IntcodeError};
+use std::error::Error;
+
+fn restore_gravity(program: &Intcode) -> Result<i64, IntcodeError> {
+    program.run_patched(&[(1, 12), (2, 2)])
 }
 
-impl Opcode {
-    pub fn new(int: &usize) -> Result<Opcode, &'static str> {
-        match int {
-            1 => Ok(Opcode::Add),
-            2 => Ok(Opcode::Multiply),
-            99 => Ok(Opcode::Halt),
-            _ => Err("Unknown Opcode."),
-        }
-    }
-
-    fn execute(&self, position: usize, data: &mut Vec<usize>) -> usize {
-        let input = data[data[position + 1]];
-        let other_input = data[data[position + 2]];
-        let target = data[position + 3];
-
-        let value = match self {
-            Self::Add => input + other_input,
-            Self::Multiply => input * other_input,
-            _ => 0,
-        };
-
-        data[target] = value;
-        return position + 4;
-    }
-
-    fn process(data: &mut Vec<usize>) -> Result<usize, &'static str> {
-        let mut position = 0;
-        while position < data.len() {
-            let optcode = Opcode::new(&data[position])?;
-
-            if let Self::Halt = optcode {
-                return Ok(data[0]);
-            }
-            position = optcode.execute(position, data);
-        }
-        Err("Reached end of data without encountering Halt (99).")
-    }
-}
-
-fn restore_gravity(data: &mut Vec<usize>) {
-    data[1] = 12;
-    data[2] = 2;
-}
-
-fn restore_computer() -> Result<(), &'static str> {
-    let mut data = vec![
-        1, 0, 0, 3, 1, 1, 2, 3, 1, 3, 4, 3, 1, 5, 0, 3, 2, 9, 1, 19, 1, 19, 5, 23, 1, 23, 6, 27, 2,
-        9, 27, 31, 1, 5, 31, 35, 1, 35, 10, 39, 1, 39, 10, 43, 2, 43, 9, 47, 1, 6, 47, 51, 2, 51,
-        6, 55, 1, 5, 55, 59, 2, 59, 10, 63, 1, 9, 63, 67, 1, 9, 67, 71, 2, 71, 6, 75, 1, 5, 75, 79,
-        1, 5, 79, 83, 1, 9, 83, 87, 2, 87, 10, 91, 2, 10, 91, 95, 1, 95, 9, 99, 2, 99, 9, 103, 2,
-        10, 103, 107, 2, 9, 107, 111, 1, 111, 5, 115, 1, 115, 2, 119, 1, 119, 6, 0, 99, 2, 0, 14,
-        0,
-    ];
-
+fn restore_computer(program: &Intcode) -> Result<(), IntcodeError> {
     println!("Restoring gravity...");
-    restore_gravity(&mut data);
-
-    println!("Running program...");
-    let pos0 = Opcode::process(&mut data)?;
+    let pos0 = restore_gravity(program)?;
 
     println!("Done. First position is {}", pos0);
     Ok(())
 }
 
-fn solve_gravity_assist() -> Result<(), &'static str> {
-    let initial_memory = vec![
-        1, 0, 0, 3, 1, 1, 2, 3, 1, 3, 4, 3, 1, 5, 0, 3, 2, 9, 1, 19, 1, 19, 5, 23, 1, 23, 6, 27, 2,
-        9, 27, 31, 1, 5, 31, 35, 1, 35, 10, 39, 1, 39, 10, 43, 2, 43, 9, 47, 1, 6, 47, 51, 2, 51,
-        6, 55, 1, 5, 55, 59, 2, 59, 10, 63, 1, 9, 63, 67, 1, 9, 67, 71, 2, 71, 6, 75, 1, 5, 75, 79,
-        1, 5, 79, 83, 1, 9, 83, 87, 2, 87, 10, 91, 2, 10, 91, 95, 1, 95, 9, 99, 2, 99, 9, 103, 2,
-        10, 103, 107, 2, 9, 107, 111, 1, 111, 5, 115, 1, 115, 2, 119, 1, 119, 6, 0, 99, 2, 0, 14,
-        0,
-    ];
-
-    for noun in 0..100 {
-        for verb in 0..100 {
-            let mut data = initial_memory.clone();
-            data[1] = noun;
-            data[2] = verb;
-
-            if Opcode::process(&mut data)? == 19690720 {
-                println!("Found valid inputs {}", 100 * noun + verb);
-                return Ok(());
-            }
-        }
+fn solve_gravity_assist(program: &Intcode) -> Result<(), SearchError> {
+    let search = Search::new(vec![(1, 0..100), (2, 0..100)], 0, 19690720);
+
+    if let Some(solution) = search.solve(program)? {
+        let (noun, verb) = (solution[0], solution[1]);
+        println!("Found valid inputs {}", 100 * noun + verb);
     }
 
     Ok(())
 }
 
-fn main() {
-    restore_computer().expect("Failed to restore computer.");
-    solve_gravity_assist().expect("Failed to solve gravity assist.");
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut program = Intcode::from_file("inputs/day02.txt")?;
+    program.set_instruction_set(InstructionSet::day02());
+
+    restore_computer(&program)?;
+    solve_gravity_assist(&program)?;
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn new_optcode() {
-        assert_eq!(Opcode::new(&1).unwrap(), Opcode::Add);
-        assert_eq!(Opcode::new(&2).unwrap(), Opcode::Multiply);
-        assert_eq!(Opcode::new(&99).unwrap(), Opcode::Halt);
-    }
+    fn process(data: Vec<i64>) -> Result<Vec<i64>, IntcodeError> {
+        let mut program = Intcode::new(data);
+        program.run(vec![])?;
 
-    #[test]
-    #[should_panic]
-    fn unknown_optcode() {
-        Opcode::new(&3).unwrap();
+        let memory = program.memory();
+        Ok((0..memory.len())
+            .map(|address| memory.get(address))
+            .collect())
     }
 
     #[test]
-    fn execute_add() {
-        let mut data = vec![1, 0, 3, 2];
-        let new_position = Opcode::Add.execute(0, &mut data);
-
-        assert_eq!(data[2], 3);
-        assert_eq!(new_position, 4);
+    fn process_1() -> Result<(), IntcodeError> {
+        assert_eq!(process(vec![1, 0, 0, 0, 99])?, vec![2, 0, 0, 0, 99]);
+        Ok(())
     }
 
     #[test]
-    fn execute_mult() {
-        let mut data = vec![0, 3, 2, 2];
-        let new_position = Opcode::Multiply.execute(0, &mut data);
-
-        assert_eq!(data[2], 4);
-        assert_eq!(new_position, 4);
+    fn process_2() -> Result<(), IntcodeError> {
+        assert_eq!(process(vec![2, 3, 0, 3, 99])?, vec![2, 3, 0, 6, 99]);
+        Ok(())
     }
 
     #[test]
-    fn process_1() -> Result<(), &'static str> {
-        let mut data = vec![1, 0, 0, 0, 99];
-        Opcode::process(&mut data)?;
-
-        assert_eq!(vec![2, 0, 0, 0, 99], data);
+    fn process_3() -> Result<(), IntcodeError> {
+        assert_eq!(
+            process(vec![2, 4, 4, 5, 99, 0])?,
+            vec![2, 4, 4, 5, 99, 9_801]
+        );
         Ok(())
     }
 
     #[test]
-    fn process_2() -> Result<(), &'static str> {
-        let mut data = vec![2, 3, 0, 3, 99];
-        Opcode::process(&mut data)?;
+    fn process_4() -> Result<(), IntcodeError> {
+        let memory = process(vec![1, 1, 1, 4, 99, 5, 6, 0, 99])?;
 
-        assert_eq!(vec![2, 3, 0, 6, 99], data);
+        assert_eq!(memory, vec![30, 1, 1, 4, 2, 5, 6, 0, 99]);
+        assert_eq!(memory[0], 30);
         Ok(())
     }
 
     #[test]
-    fn process_3() -> Result<(), &'static str> {
-        let mut data = vec![2, 4, 4, 5, 99, 0];
-        Opcode::process(&mut data)?;
-
-        assert_eq!(vec![2, 4, 4, 5, 99, 9_801], data);
-        Ok(())
+    fn missing_halt() {
+        assert!(matches!(
+            process(vec![1, 0, 0, 0]),
+            Err(IntcodeError::EndOfProgram { position: 4, .. })
+        ));
     }
 
     #[test]
-    fn process_4() -> Result<(), &'static str> {
-        let mut data = vec![1, 1, 1, 4, 99, 5, 6, 0, 99];
-        let pos0 = Opcode::process(&mut data)?;
-
-        assert_eq!(vec![30, 1, 1, 4, 2, 5, 6, 0, 99], data);
-        assert_eq!(30, pos0);
+    fn process_day2_part1() -> Result<(), IntcodeError> {
+        let program = Intcode::from_file("inputs/day02.txt")?;
+        assert_eq!(restore_gravity(&program)?, 3516593);
         Ok(())
     }
 
     #[test]
-    fn restore_gravity_inplace() {
-        let mut data = vec![0, 0, 0, 0];
-        restore_gravity(&mut data);
+    fn process_day2_part2() -> Result<(), SearchError> {
+        let program = Intcode::from_file("inputs/day02.txt")?;
+        let search = Search::new(vec![(1, 0..100), (2, 0..100)], 0, 19690720);
 
-        assert_eq!(vec![0, 12, 2, 0], data);
-    }
-
-    #[test]
-    fn process_day2_part1() -> Result<(), &'static str> {
-        let mut data = vec![
-            1, 0, 0, 3, 1, 1, 2, 3, 1, 3, 4, 3, 1, 5, 0, 3, 2, 9, 1, 19, 1, 19, 5, 23, 1, 23, 6,
-            27, 2, 9, 27, 31, 1, 5, 31, 35, 1, 35, 10, 39, 1, 39, 10, 43, 2, 43, 9, 47, 1, 6, 47,
-            51, 2, 51, 6, 55, 1, 5, 55, 59, 2, 59, 10, 63, 1, 9, 63, 67, 1, 9, 67, 71, 2, 71, 6,
-            75, 1, 5, 75, 79, 1, 5, 79, 83, 1, 9, 83, 87, 2, 87, 10, 91, 2, 10, 91, 95, 1, 95, 9,
-            99, 2, 99, 9, 103, 2, 10, 103, 107, 2, 9, 107, 111, 1, 111, 5, 115, 1, 115, 2, 119, 1,
-            119, 6, 0, 99, 2, 0, 14, 0,
-        ];
-        restore_gravity(&mut data);
-        assert_eq!(Opcode::process(&mut data)?, 3516593);
+        assert_eq!(search.solve_linear(&program)?, Some(vec![77, 49]));
+        assert_eq!(search.brute_force(&program)?, Some(vec![77, 49]));
         Ok(())
     }
 }
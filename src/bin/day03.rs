@@ -150,7 +150,7 @@ impl Wire {
         let mut intersections: Vec<Point> = Vec::new();
         for line in self.lines.iter() {
             for other_line in other.lines.iter() {
-                if let Some(point) = line.intersect(&other_line) {
+                if let Some(point) = line.intersect(other_line) {
                     if point != Point::ORIGIN {
                         intersections.push(point);
                     }
@@ -163,10 +163,10 @@ impl Wire {
     fn steps(&self, target: &Point) -> Option<i32> {
         let mut count: i32 = 0;
         for line in self.lines.iter() {
-            if line.crosses_point(&target) {
-                return Some(count + line.start.distance(&target));
+            if line.crosses_point(target) {
+                return Some(count + line.start.distance(target));
             }
-            count = count + line.length();
+            count += line.length();
         }
         None
     }
@@ -174,35 +174,27 @@ impl Wire {
     fn find_min_delay(&self, other: &Wire) -> Option<i32> {
         self.intersections(other)
             .iter()
-            .map(|point| {
-                let steps = self.steps(&point);
-                let other_steps = other.steps(&point);
+            .filter_map(|point| {
+                let steps = self.steps(point);
+                let other_steps = other.steps(point);
 
                 match (steps, other_steps) {
                     (Some(steps), Some(other_steps)) => Some(steps + other_steps),
                     _ => None,
                 }
             })
-            .filter(|option| option.is_some())
-            .map(|option| option.unwrap())
             .min()
     }
 
     fn closest_intersection(&self, other: &Wire) -> Option<Point> {
         let mut intersections = self.intersections(other);
-        intersections.sort_by(|a, b| a.length().cmp(&b.length()));
+        intersections.sort_by_key(|a| a.length());
 
-        match intersections.iter().next() {
-            Some(point) => Some(point.clone()),
-            None => None,
-        }
+        intersections.first().cloned()
     }
 
     fn closest_distance(&self, other: &Wire) -> Option<i32> {
-        match self.closest_intersection(other) {
-            Some(point) => Some(point.length()),
-            None => None,
-        }
+        self.closest_intersection(other).map(|point| point.length())
     }
 }
 
@@ -7,20 +7,20 @@ fn to_digits(mut number: u32, digit_count: u32) -> Vec<u32> {
         assert!(digit < 10, "Digit was {}", digit);
 
         digits.push(digit);
-        number = number - digit * current_position;
+        number -= digit * current_position;
     }
 
     digits
 }
 
-fn has_pair_digits(digits: &Vec<u32>) -> bool {
+fn has_pair_digits(digits: &[u32]) -> bool {
     digits
         .iter()
         .zip(digits.iter().skip(1))
         .any(|(a, b)| a == b)
 }
 
-fn has_exact_pair_digits(digits: &Vec<u32>) -> bool {
+fn has_exact_pair_digits(digits: &[u32]) -> bool {
     assert_eq!(digits.len(), 6);
 
     let is_exact_pair = |pos| {
@@ -39,7 +39,7 @@ fn has_exact_pair_digits(digits: &Vec<u32>) -> bool {
     pair1 || pair2 || pair3 || pair4 || pair5
 }
 
-fn has_increasing_digits(digits: &Vec<u32>) -> bool {
+fn has_increasing_digits(digits: &[u32]) -> bool {
     let mut previous = &0;
 
     for digit in digits.iter() {
@@ -90,27 +90,27 @@ mod tests {
 
     #[test]
     fn should_find_pairs() {
-        assert!(has_pair_digits(&vec![1, 0, 0]));
-        assert!(has_pair_digits(&vec![1, 1, 0]));
-        assert!(has_pair_digits(&vec![1, 2, 3, 4, 5, 5, 6, 7]));
-        assert!(!has_pair_digits(&vec![1, 0]));
-        assert!(!has_pair_digits(&vec![1]));
-        assert!(!has_pair_digits(&vec![1, 2, 3, 4]));
+        assert!(has_pair_digits(&[1, 0, 0]));
+        assert!(has_pair_digits(&[1, 1, 0]));
+        assert!(has_pair_digits(&[1, 2, 3, 4, 5, 5, 6, 7]));
+        assert!(!has_pair_digits(&[1, 0]));
+        assert!(!has_pair_digits(&[1]));
+        assert!(!has_pair_digits(&[1, 2, 3, 4]));
     }
 
     #[test]
     fn should_find_increasing_digits() {
-        assert!(has_increasing_digits(&vec![1, 2, 2, 3]));
-        assert!(has_increasing_digits(&vec![2, 2, 2, 2]));
-        assert!(has_increasing_digits(&vec![1]));
-        assert!(!has_increasing_digits(&vec![1, 2, 2, 3, 0]));
+        assert!(has_increasing_digits(&[1, 2, 2, 3]));
+        assert!(has_increasing_digits(&[2, 2, 2, 2]));
+        assert!(has_increasing_digits(&[1]));
+        assert!(!has_increasing_digits(&[1, 2, 2, 3, 0]));
     }
 
     #[test]
     fn should_find_exact_pairs() {
-        assert!(has_exact_pair_digits(&vec![1, 1, 1, 1, 2, 2]));
-        assert!(!has_exact_pair_digits(&vec![1, 1, 1, 2, 2, 2]));
-        assert!(has_exact_pair_digits(&vec![1, 2, 3, 4, 2, 2]));
-        assert!(!has_exact_pair_digits(&vec![1, 2, 3, 4, 5, 6]));
+        assert!(has_exact_pair_digits(&[1, 1, 1, 1, 2, 2]));
+        assert!(!has_exact_pair_digits(&[1, 1, 1, 2, 2, 2]));
+        assert!(has_exact_pair_digits(&[1, 2, 3, 4, 2, 2]));
+        assert!(!has_exact_pair_digits(&[1, 2, 3, 4, 5, 6]));
     }
 }
@@ -45,7 +45,7 @@ impl Object {
         Object {
             name: self.name.to_string(),
             orbits_around: match &self.orbits_around {
-                Some(object) => Some(Rc::clone(&object)),
+                Some(object) => Some(Rc::clone(object)),
                 None => None,
             },
         }
@@ -70,7 +70,7 @@ impl Map {
 
             objects
                 .entry(center.to_string())
-                .or_insert_with(|| Object::empty(&center));
+                .or_insert_with(|| Object::empty(center));
 
             objects
                 .entry(object.to_string())
@@ -154,12 +154,12 @@ mod tests {
 
     #[test]
     fn rc_objects_count() {
-        let COM = Object::empty("COM");
-        let mut B = Object::empty("B");
-        assert_eq!(COM.borrow().count_orbits(), 0);
+        let com = Object::empty("COM");
+        let b = Object::empty("B");
+        assert_eq!(com.borrow().count_orbits(), 0);
 
-        B.borrow_mut().orbits_around.replace(Rc::clone(&COM));
-        assert_eq!(B.borrow().count_orbits(), 1);
+        b.borrow_mut().orbits_around.replace(Rc::clone(&com));
+        assert_eq!(b.borrow().count_orbits(), 1);
     }
 
     #[test]
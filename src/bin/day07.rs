@@ -2,42 +2,135 @@ extern crate adventofcode2019;
 extern crate permutohedron;
 
 use adventofcode2019::intcode;
+use adventofcode2019::intcode::compile::{Compiled, CompiledMachine};
 use permutohedron::LexicalPermutation;
+use std::error::Error;
+use std::fmt;
+use std::ops::RangeInclusive;
 
-fn program_amplifiers(phase_signals: &Vec<i32>, program: &intcode::Intcode) -> i32 {
-    assert_eq!(phase_signals.len(), 5);
+/// A chain of amplifiers, each running its own copy of the same program.
+///
+/// Every amplifier reads its phase setting first. Afterwards a signal is passed
+/// through the chain: each amplifier reads the output of the previous one.
+struct AmplifierChain {
+    stages: Vec<CompiledMachine>,
+}
+
+#[derive(Debug)]
+enum AmplifierError {
+    Machine(intcode::IntcodeError),
+    /// The chain has no amplifiers, or there are no phase settings to try.
+    NoAmplifiers,
+    /// Every amplifier halted before the final one emitted a signal.
+    NoSignal,
+}
+
+impl AmplifierChain {
+    fn new(program: &Compiled, phase_signals: &[i64]) -> AmplifierChain {
+        let stages = phase_signals
+            .iter()
+            .map(|phase| {
+                let mut stage = program.machine();
+                stage.provide_input(*phase);
+                stage
+            })
+            .collect();
 
-    let mut program_a = program.clone();
-    let mut program_b = program.clone();
-    let mut program_c = program.clone();
-    let mut program_d = program.clone();
-    let mut program_e = program.clone();
+        AmplifierChain { stages }
+    }
+
+    /// Passes `signal` through all amplifiers and returns the last signal of
+    /// the final amplifier.
+    ///
+    /// In feedback mode, the output of the final amplifier is fed back into the
+    /// first one, until every amplifier halted.
+    fn run(&mut self, mut signal: i64, feedback: bool) -> Result<i64, AmplifierError> {
+        let last = self
+            .stages
+            .len()
+            .checked_sub(1)
+            .ok_or(AmplifierError::NoAmplifiers)?;
+        let mut thruster_signal = None;
 
-    let outputs_a = program_a.run(vec![phase_signals[0], 0]);
-    assert_eq!(outputs_a.len(), 1);
+        loop {
+            let mut running = false;
 
-    let ouputs_b = program_b.run(vec![phase_signals[1], outputs_a[0]]);
-    assert_eq!(ouputs_b.len(), 1);
+            for (index, stage) in self.stages.iter_mut().enumerate() {
+                stage.provide_input(signal);
 
-    let outputs_c = program_c.run(vec![phase_signals[2], ouputs_b[0]]);
-    assert_eq!(outputs_c.len(), 1);
+                match stage.resume()? {
+                    intcode::Status::Output(value) => {
+                        signal = value;
+                        running = true;
 
-    let outputs_d = program_d.run(vec![phase_signals[3], outputs_c[0]]);
-    assert_eq!(outputs_d.len(), 1);
+                        if index == last {
+                            thruster_signal = Some(value);
+                        }
+                    }
+                    intcode::Status::Halted => continue,
+                    _ => {
+                        return Err(AmplifierError::Machine(
+                            intcode::IntcodeError::MissingInput {
+                                position: stage.position(),
+                                opcode: stage.get(stage.position()),
+                            },
+                        ));
+                    }
+                }
+            }
 
-    let outputs_e = program_e.run(vec![phase_signals[4], outputs_d[0]]);
-    assert_eq!(outputs_e.len(), 1);
+            if !feedback || !running {
+                break;
+            }
+        }
 
-    outputs_e[0]
+        thruster_signal.ok_or(AmplifierError::NoSignal)
+    }
+}
+
+impl fmt::Display for AmplifierError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AmplifierError::Machine(error) => write!(f, "{}", error),
+            AmplifierError::NoAmplifiers => write!(f, "There are no amplifiers"),
+            AmplifierError::NoSignal => write!(f, "Amplifiers halted without a signal"),
+        }
+    }
+}
+
+impl Error for AmplifierError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AmplifierError::Machine(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<intcode::IntcodeError> for AmplifierError {
+    fn from(error: intcode::IntcodeError) -> Self {
+        AmplifierError::Machine(error)
+    }
+}
+
+fn program_amplifiers(phase_signals: &[i64], program: &Compiled) -> Result<i64, AmplifierError> {
+    AmplifierChain::new(program, phase_signals).run(0, false)
+}
+
+fn program_feedback_amplifiers(
+    phase_signals: &[i64],
+    program: &Compiled,
+) -> Result<i64, AmplifierError> {
+    AmplifierChain::new(program, phase_signals).run(0, true)
 }
 
 struct Permutator {
-    data: Vec<i32>,
+    data: Vec<i64>,
     permutated: bool,
 }
 
 impl Permutator {
-    fn new(data: Vec<i32>) -> Permutator {
+    fn new(data: Vec<i64>) -> Permutator {
         Permutator {
             data,
             permutated: false,
@@ -46,9 +139,9 @@ impl Permutator {
 }
 
 impl Iterator for Permutator {
-    type Item = Vec<i32>;
+    type Item = Vec<i64>;
 
-    fn next(&mut self) -> Option<Vec<i32>> {
+    fn next(&mut self) -> Option<Vec<i64>> {
         if !self.permutated {
             self.permutated = true;
             return Some(self.data.clone());
@@ -62,20 +155,44 @@ impl Iterator for Permutator {
     }
 }
 
-fn generate_phase_signals() -> Permutator {
-    Permutator::new(vec![0, 1, 2, 3, 4])
+fn generate_phase_signals(phases: RangeInclusive<i64>) -> Permutator {
+    Permutator::new(phases.collect())
 }
 
-fn find_max_amplification(program: &intcode::Intcode) -> i32 {
-    generate_phase_signals()
-        .map(|signals| program_amplifiers(&signals, program))
+fn find_max_signal<F>(phases: RangeInclusive<i64>, amplify: F) -> Result<i64, AmplifierError>
+where
+    F: Fn(&[i64]) -> Result<i64, AmplifierError>,
+{
+    let signals = generate_phase_signals(phases)
+        .map(|signals| amplify(&signals))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    signals
+        .into_iter()
         .max()
-        .expect("Failed to compute max amplification!")
+        .ok_or(AmplifierError::NoAmplifiers)
+}
+
+fn find_max_amplification(program: &intcode::Intcode) -> Result<i64, AmplifierError> {
+    let program = Compiled::new(program);
+    find_max_signal(0..=4, |signals| program_amplifiers(signals, &program))
 }
 
-fn main() {
-    let program = intcode::Intcode::from_file("inputs/day07.txt");
-    println!("Max Signal: {}", find_max_amplification(&program));
+fn find_max_feedback_amplification(program: &intcode::Intcode) -> Result<i64, AmplifierError> {
+    let program = Compiled::new(program);
+    find_max_signal(5..=9, |signals| {
+        program_feedback_amplifiers(signals, &program)
+    })
+}
+
+fn main() -> Result<(), AmplifierError> {
+    let program = intcode::Intcode::from_file("inputs/day07.txt")?;
+    println!("Max Signal: {}", find_max_amplification(&program)?);
+    println!(
+        "Max Feedback Signal: {}",
+        find_max_feedback_amplification(&program)?
+    );
+    Ok(())
 }
 
 #[cfg(test)]
@@ -83,29 +200,122 @@ mod tests {
     use super::*;
 
     #[test]
-    fn sample_amplifiers_1() {
+    fn sample_amplifiers_1() -> Result<(), AmplifierError> {
         let signals = vec![4, 3, 2, 1, 0];
-        let program = intcode::Intcode::new(vec![
+        let program = Compiled::new(&intcode::Intcode::new(vec![
             3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
-        ]);
-        assert_eq!(program_amplifiers(&signals, &program), 43210);
+        ]));
+        assert_eq!(program_amplifiers(&signals, &program)?, 43210);
+        Ok(())
     }
 
     #[test]
-    fn max_amplification_1() {
+    fn max_amplification_1() -> Result<(), AmplifierError> {
         let program = intcode::Intcode::new(vec![
             3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
         ]);
-        assert_eq!(find_max_amplification(&program), 43210);
+        assert_eq!(find_max_amplification(&program)?, 43210);
+        Ok(())
     }
 
     #[test]
-    fn max_amplification_2() {
+    fn max_amplification_2() -> Result<(), AmplifierError> {
         let program = intcode::Intcode::new(vec![
             3, 23, 3, 24, 1002, 24, 10, 24, 1002, 23, -1, 23, 101, 5, 23, 23, 1, 24, 23, 23, 4, 23,
             99, 0, 0,
         ]);
-        assert_eq!(find_max_amplification(&program), 54321);
+        assert_eq!(find_max_amplification(&program)?, 54321);
+        Ok(())
+    }
+
+    #[test]
+    fn sample_amplifiers_3_stages() -> Result<(), AmplifierError> {
+        let signals = vec![4, 3, 2];
+        let program = Compiled::new(&intcode::Intcode::new(vec![
+            3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
+        ]));
+        assert_eq!(program_amplifiers(&signals, &program)?, 432);
+        Ok(())
+    }
+
+    #[test]
+    fn sample_feedback_amplifiers_1() -> Result<(), AmplifierError> {
+        let signals = vec![9, 8, 7, 6, 5];
+        let program = Compiled::new(&intcode::Intcode::new(vec![
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ]));
+        assert_eq!(program_feedback_amplifiers(&signals, &program)?, 139629729);
+        Ok(())
+    }
+
+    #[test]
+    fn max_feedback_amplification_1() -> Result<(), AmplifierError> {
+        let program = intcode::Intcode::new(vec![
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ]);
+        assert_eq!(find_max_feedback_amplification(&program)?, 139629729);
+        Ok(())
+    }
+
+    #[test]
+    fn max_feedback_amplification_2() -> Result<(), AmplifierError> {
+        let program = intcode::Intcode::new(vec![
+            3, 52, 1001, 52, -5, 52, 3, 53, 1, 52, 56, 54, 1007, 54, 5, 55, 1005, 55, 26, 1001, 54,
+            -5, 54, 1105, 1, 12, 1, 53, 54, 53, 1008, 54, 0, 55, 1001, 55, 1, 55, 2, 53, 55, 53, 4,
+            53, 1001, 56, -1, 56, 1005, 56, 6, 99, 0, 0, 0, 0, 10,
+        ]);
+        assert_eq!(find_max_feedback_amplification(&program)?, 18216);
+        Ok(())
+    }
+
+    #[test]
+    fn no_amplifiers() {
+        let program = Compiled::new(&intcode::Intcode::new(vec![99]));
+        assert!(matches!(
+            program_amplifiers(&[], &program),
+            Err(AmplifierError::NoAmplifiers)
+        ));
+    }
+
+    #[test]
+    fn halt_without_signal() {
+        let program = Compiled::new(&intcode::Intcode::new(vec![3, 0, 99]));
+        assert!(matches!(
+            program_amplifiers(&[0, 1], &program),
+            Err(AmplifierError::NoSignal)
+        ));
+        assert!(matches!(
+            program_feedback_amplifiers(&[0, 1], &program),
+            Err(AmplifierError::NoSignal)
+        ));
+    }
+
+    #[test]
+    fn hanging_phase() {
+        // Passes the signal on, but loops forever on phase 0.
+        let mut program =
+            intcode::Intcode::new(vec![3, 11, 1006, 11, 2, 3, 12, 4, 12, 99, 0, 0, 0]);
+        program.enable_cycle_detection();
+        let program = Compiled::new(&program);
+
+        assert_eq!(
+            AmplifierChain::new(&program, &[1, 2])
+                .run(7, false)
+                .unwrap(),
+            7
+        );
+        assert!(matches!(
+            AmplifierChain::new(&program, &[1, 0]).run(7, false),
+            Err(AmplifierError::Machine(
+                intcode::IntcodeError::InfiniteLoop {
+                    entry: 2,
+                    period: 1,
+                    ..
+                }
+            ))
+        ));
     }
 
     #[test]
@@ -0,0 +1,32 @@
+extern crate adventofcode2019;
+use adventofcode2019::intcode;
+use adventofcode2019::intcode::debugger::{Debugger, HELP};
+use std::env;
+use std::io;
+use std::io::prelude::*;
+
+fn main() -> Result<(), intcode::IntcodeError> {
+    let filename = env::args().nth(1).expect("Usage: debugger <program file>");
+    let mut debugger = Debugger::new(intcode::Intcode::from_file(&filename)?);
+
+    println!("{}", HELP);
+    print!("{}", debugger.execute("list 0 1"));
+
+    let stdin = io::stdin();
+    loop {
+        print!("(icd) ");
+        io::stdout().flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+
+        match line.trim() {
+            "q" | "quit" => break,
+            command => print!("{}", debugger.execute(command)),
+        }
+    }
+
+    Ok(())
+}
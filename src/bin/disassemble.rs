@@ -0,0 +1,13 @@
+extern crate adventofcode2019;
+use adventofcode2019::intcode;
+use std::env;
+
+fn main() -> Result<(), intcode::IntcodeError> {
+    let filename = env::args()
+        .nth(1)
+        .expect("Usage: disassemble <program file>");
+    let program = intcode::Intcode::from_file(&filename)?;
+
+    print!("{}", intcode::disassemble::listing(program.memory()));
+    Ok(())
+}
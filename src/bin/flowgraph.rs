@@ -0,0 +1,27 @@
+extern crate adventofcode2019;
+use adventofcode2019::intcode;
+use adventofcode2019::intcode::flow::FlowGraph;
+use std::env;
+
+const USAGE: &str = "Usage: flowgraph <program file> [<address>=<value>...]";
+
+fn main() -> Result<(), intcode::IntcodeError> {
+    let mut args = env::args().skip(1);
+    let filename = args.next().expect(USAGE);
+    let mut program = intcode::Intcode::from_file(&filename)?;
+
+    // Patches let programs that rewrite their own code before it runs, like
+    // the diagnostic program of day 5, be analyzed as they would run.
+    for patch in args {
+        let mut parts = patch.splitn(2, '=');
+        let address = parts.next().and_then(|address| address.parse().ok());
+        let value = parts.next().and_then(|value| value.parse().ok());
+        match (address, value) {
+            (Some(address), Some(value)) => program.patch(address, value),
+            _ => panic!("{}", USAGE),
+        }
+    }
+
+    print!("{}", FlowGraph::build(program.memory()).dot());
+    Ok(())
+}
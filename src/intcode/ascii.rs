@@ -0,0 +1,149 @@
+//! Text I/O for programs that talk in ASCII, like text adventures or robots
+//! that take commands.
+//!
+//! Inputs are lines of text, which the program reads as character codes
+//! ending in a newline. Outputs below 128 are characters. Anything else, e.g.
+//! the answer a program prints after its text, is shown as a number.
+
+use super::{Intcode, IntcodeError, Status};
+use std::fmt::Write;
+
+/// The character codes of a line of input, ending in a newline.
+pub fn encode(line: &str) -> Vec<i64> {
+    line.chars()
+        .chain(std::iter::once('\n'))
+        .map(|c| i64::from(u32::from(c)))
+        .collect()
+}
+
+/// Renders outputs as text.
+pub fn decode(outputs: &[i64]) -> String {
+    let mut text = String::new();
+    for output in outputs {
+        match *output {
+            code @ 0..=127 => text.push(code as u8 as char),
+            number => write!(text, "{}", number).unwrap(),
+        }
+    }
+    text
+}
+
+/// An Intcode machine behind a text interface.
+pub struct Terminal {
+    machine: Intcode,
+    halted: bool,
+}
+
+impl Terminal {
+    pub fn new(machine: Intcode) -> Terminal {
+        Terminal {
+            machine,
+            halted: false,
+        }
+    }
+
+    pub fn machine(&self) -> &Intcode {
+        &self.machine
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Runs the program until it waits for a line or halts, and returns what
+    /// it printed.
+    pub fn read(&mut self) -> Result<String, IntcodeError> {
+        if !self.halted {
+            self.halted = self.machine.pump()? == Status::Halted;
+        }
+        Ok(decode(&self.machine.take_outputs()))
+    }
+
+    /// Sends a line to the program, and returns what it printed in response
+    /// until it waits for the next line or halts.
+    pub fn send(&mut self, line: &str) -> Result<String, IntcodeError> {
+        for code in encode(line) {
+            self.machine.provide_input(code);
+        }
+        self.read()
+    }
+
+    /// Sends every line of `script` in turn and returns everything the
+    /// program printed. Lines after the program halted are ignored. Once the
+    /// script is used up, the program may still be waiting for more, which
+    /// `is_halted` tells.
+    pub fn run_script(&mut self, script: &str) -> Result<String, IntcodeError> {
+        let mut text = self.read()?;
+        for line in script.lines() {
+            if self.halted {
+                break;
+            }
+            text += &self.send(line)?;
+        }
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::assemble::assemble;
+    use super::*;
+
+    /// Asks for two lines, echoes them and prints a number once it is done.
+    const PARROT: &str = "
+        prompt: out #63
+                out #10
+        read:   in [char]
+                out [char]
+                eq [char], #10, [newline]
+                jf [newline], #read
+                add [lines], #1, [lines]
+                lt [lines], #2, [more]
+                jt [more], #prompt
+                out #1000
+                hlt
+        char:   data 0
+        newline: data 0
+        lines:  data 0
+        more:   data 0
+    ";
+
+    fn parrot() -> Terminal {
+        Terminal::new(Intcode::new(assemble(PARROT).unwrap()))
+    }
+
+    #[test]
+    fn encode_and_decode() {
+        assert_eq!(encode("NOT A J"), vec![78, 79, 84, 32, 65, 32, 74, 10]);
+        assert_eq!(encode(""), vec![10]);
+        assert_eq!(decode(&[72, 105, 10, 19_349_939]), "Hi\n19349939");
+        assert_eq!(decode(&[128, -1]), "128-1");
+    }
+
+    #[test]
+    fn interactive() {
+        let mut terminal = parrot();
+
+        assert_eq!(terminal.read().unwrap(), "?\n");
+        assert_eq!(terminal.send("north").unwrap(), "north\n?\n");
+        assert!(!terminal.is_halted());
+        assert_eq!(terminal.send("take key").unwrap(), "take key\n1000");
+        assert!(terminal.is_halted());
+
+        assert_eq!(terminal.send("south").unwrap(), "");
+    }
+
+    #[test]
+    fn script() {
+        let mut terminal = parrot();
+        assert_eq!(
+            terminal.run_script("a\nb\nc\n").unwrap(),
+            "?\na\n?\nb\n1000"
+        );
+        assert!(terminal.is_halted());
+
+        let mut terminal = parrot();
+        assert_eq!(terminal.run_script("a").unwrap(), "?\na\n?\n");
+        assert!(!terminal.is_halted());
+    }
+}
@@ -0,0 +1,459 @@
+//! An assembler for a small Intcode assembly language.
+//!
+//! The language reads like the output of the disassembler:
+//!
+//! ```text
+//! ; Echoes inputs until it reads a zero.
+//! loop:   in [value]
+//!         jf [value], #end
+//!         out [value]
+//!         jt #1, #loop
+//! end:    hlt
+//! value:  data 0
+//! ```
+//!
+//! Every line holds an optional label, followed by an instruction or a `data`
+//! directive. Operands are written as `[address]` for position mode, `#value`
+//! for immediate mode and `rb+offset` or `rb-offset` for relative mode.
+//! Addresses and values are numbers or labels, optionally with an added or
+//! subtracted number, e.g. `[table+2]`. A numeric label like `12:` asserts the
+//! address of the line, so listings of the disassembler can be assembled again.
+//! Custom operations are written as `op` followed by their opcode, e.g.
+//! `op42 [x], #1`, and take any number of operands.
+
+use super::Operation;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum AssembleErrorKind {
+    UnknownMnemonic(String),
+    WrongOperandCount {
+        mnemonic: String,
+        expected: usize,
+        found: usize,
+    },
+    MalformedOperand(String),
+    MalformedLabel(String),
+    ImmediateTarget(String),
+    DuplicateLabel(String),
+    UnresolvedLabel(String),
+    AddressMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    /// A label with its offset, or an opcode with its modes, does not fit
+    /// into a word.
+    Overflow(String),
+}
+
+/// A diagnostic for a line of assembly that could not be assembled.
+#[derive(Debug, PartialEq)]
+pub struct AssembleError {
+    /// The line number, starting at 1.
+    pub line: usize,
+    pub kind: AssembleErrorKind,
+}
+
+#[derive(Debug)]
+enum Value {
+    Number(i64),
+    Label { name: String, offset: i64 },
+}
+
+#[derive(Debug)]
+enum Mode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+/// A word of the program that still needs its labels resolved.
+struct Item {
+    line: usize,
+    value: Value,
+}
+
+/// Assembles a program into a memory image that `Intcode::new` can load.
+pub fn assemble(source: &str) -> Result<Vec<i64>, AssembleError> {
+    let mut labels: HashMap<String, usize> = HashMap::new();
+    let mut items: Vec<Item> = vec![];
+
+    for (index, line) in source.lines().enumerate() {
+        let number = index + 1;
+        let error = |kind| AssembleError { line: number, kind };
+
+        let mut rest = line.split(';').next().unwrap_or("").trim();
+
+        while let Some(colon) = rest.find(':') {
+            let label = rest[..colon].trim();
+            let address = items.len();
+
+            if let Ok(expected) = label.parse::<usize>() {
+                if expected != address {
+                    return Err(error(AssembleErrorKind::AddressMismatch {
+                        expected,
+                        actual: address,
+                    }));
+                }
+            } else if is_identifier(label) {
+                if labels.insert(label.to_string(), address).is_some() {
+                    return Err(error(AssembleErrorKind::DuplicateLabel(label.to_string())));
+                }
+            } else {
+                return Err(error(AssembleErrorKind::MalformedLabel(label.to_string())));
+            }
+
+            rest = rest[colon + 1..].trim();
+        }
+
+        if rest.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, operands) = match rest.find(char::is_whitespace) {
+            Some(space) => (&rest[..space], rest[space..].trim()),
+            None => (rest, ""),
+        };
+        let operands: Vec<&str> = if operands.is_empty() {
+            vec![]
+        } else {
+            operands.split(',').map(str::trim).collect()
+        };
+
+        if mnemonic == "data" {
+            for operand in operands {
+                let value = parse_value(operand).ok_or_else(|| {
+                    error(AssembleErrorKind::MalformedOperand(operand.to_string()))
+                })?;
+                items.push(Item {
+                    line: number,
+                    value,
+                });
+            }
+            continue;
+        }
+
+        let operation = Operation::from_mnemonic(mnemonic)
+            .or_else(|| custom_operation(mnemonic, operands.len()))
+            .ok_or_else(|| error(AssembleErrorKind::UnknownMnemonic(mnemonic.to_string())))?;
+        if operands.len() != operation.arity() {
+            return Err(error(AssembleErrorKind::WrongOperandCount {
+                mnemonic: mnemonic.to_string(),
+                expected: operation.arity(),
+                found: operands.len(),
+            }));
+        }
+
+        let mut opcode = operation.opcode();
+        let mut factor: Option<i64> = Some(100);
+        let mut parameters = vec![];
+
+        for (index, operand) in operands.iter().enumerate() {
+            let (mode, value) = parse_operand(operand)
+                .ok_or_else(|| error(AssembleErrorKind::MalformedOperand(operand.to_string())))?;
+
+            let mode = match mode {
+                Mode::Position => 0,
+                Mode::Immediate if operation.target() == Some(index) => {
+                    return Err(error(AssembleErrorKind::ImmediateTarget(
+                        operand.to_string(),
+                    )));
+                }
+                Mode::Immediate => 1,
+                Mode::Relative => 2,
+            };
+            opcode = factor
+                .and_then(|factor| opcode.checked_add(mode * factor))
+                .ok_or_else(|| error(AssembleErrorKind::Overflow(mnemonic.to_string())))?;
+            factor = factor.and_then(|factor| factor.checked_mul(10));
+            parameters.push(value);
+        }
+
+        items.push(Item {
+            line: number,
+            value: Value::Number(opcode),
+        });
+        for value in parameters {
+            items.push(Item {
+                line: number,
+                value,
+            });
+        }
+    }
+
+    items
+        .into_iter()
+        .map(|item| match item.value {
+            Value::Number(number) => Ok(number),
+            Value::Label { name, offset } => {
+                let line = item.line;
+                let address = labels.get(&name).ok_or_else(|| AssembleError {
+                    line,
+                    kind: AssembleErrorKind::UnresolvedLabel(name.clone()),
+                })?;
+
+                (*address as i64)
+                    .checked_add(offset)
+                    .ok_or_else(|| AssembleError {
+                        line,
+                        kind: AssembleErrorKind::Overflow(format!("{}{:+}", name, offset)),
+                    })
+            }
+        })
+        .collect()
+}
+
+/// Parses the mnemonic of a custom operation like `op42`. Its operands are
+/// all treated as read parameters. Opcodes of built-in operations are not
+/// custom, since the machine decodes them with their own parameters.
+fn custom_operation(mnemonic: &str, arity: usize) -> Option<Operation> {
+    let opcode: i64 = mnemonic.strip_prefix("op")?.parse().ok()?;
+    if !(1..=98).contains(&opcode) || Operation::from_opcode(opcode).is_some() {
+        return None;
+    }
+
+    Some(Operation::Custom {
+        opcode,
+        arity,
+        target: None,
+    })
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => {}
+        _ => return false,
+    }
+
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_value(value: &str) -> Option<Value> {
+    if let Ok(number) = value.parse() {
+        return Some(Value::Number(number));
+    }
+
+    let (name, offset) = match value.find(['+', '-']) {
+        Some(sign) => (
+            value[..sign].trim(),
+            value[sign..].replace(' ', "").parse().ok()?,
+        ),
+        None => (value, 0),
+    };
+
+    if is_identifier(name) {
+        Some(Value::Label {
+            name: name.to_string(),
+            offset,
+        })
+    } else {
+        None
+    }
+}
+
+fn parse_operand(operand: &str) -> Option<(Mode, Value)> {
+    if operand.starts_with('[') && operand.ends_with(']') {
+        let value = parse_value(operand[1..operand.len() - 1].trim())?;
+        Some((Mode::Position, value))
+    } else if let Some(value) = operand.strip_prefix('#') {
+        Some((Mode::Immediate, parse_value(value.trim())?))
+    } else if let Some(offset) = operand.strip_prefix("rb") {
+        let offset = offset.replace(' ', "");
+        let value = match offset.as_str() {
+            "" => Value::Number(0),
+            _ if offset.starts_with('+') || offset.starts_with('-') => {
+                Value::Number(offset.parse().ok()?)
+            }
+            _ => return None,
+        };
+        Some((Mode::Relative, value))
+    } else {
+        None
+    }
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+
+        match &self.kind {
+            AssembleErrorKind::UnknownMnemonic(mnemonic) => {
+                write!(f, "unknown mnemonic `{}`", mnemonic)
+            }
+            AssembleErrorKind::WrongOperandCount {
+                mnemonic,
+                expected,
+                found,
+            } => write!(
+                f,
+                "`{}` takes {} operands, but {} were given",
+                mnemonic, expected, found
+            ),
+            AssembleErrorKind::MalformedOperand(operand) => {
+                write!(f, "malformed operand `{}`", operand)
+            }
+            AssembleErrorKind::MalformedLabel(label) => write!(f, "malformed label `{}`", label),
+            AssembleErrorKind::ImmediateTarget(operand) => write!(
+                f,
+                "target operand `{}` must not be in immediate mode",
+                operand
+            ),
+            AssembleErrorKind::DuplicateLabel(label) => {
+                write!(f, "label `{}` is defined twice", label)
+            }
+            AssembleErrorKind::UnresolvedLabel(label) => {
+                write!(f, "unresolved label `{}`", label)
+            }
+            AssembleErrorKind::AddressMismatch { expected, actual } => write!(
+                f,
+                "line is expected at address {}, but is at address {}",
+                expected, actual
+            ),
+            AssembleErrorKind::Overflow(value) => write!(f, "`{}` does not fit into a word", value),
+        }
+    }
+}
+
+impl Error for AssembleError {}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{disassemble, Intcode, Memory};
+    use super::*;
+
+    const ECHO: &str = "
+        ; Echoes inputs until it reads a zero.
+        loop:   in [value]
+                jf [value], #end
+                out [value]
+                jt #1, #loop
+        end:    hlt
+        value:  data 0
+    ";
+
+    #[test]
+    fn assemble_echo() {
+        let program = assemble(ECHO).unwrap();
+        assert_eq!(program, vec![3, 11, 1006, 11, 10, 4, 11, 1105, 1, 0, 99, 0]);
+
+        let mut machine = Intcode::new(program);
+        assert_eq!(machine.run(vec![3, 2, 1, 0]).unwrap(), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn modes_and_offsets() {
+        let program = assemble(
+            "
+            arb #table
+            add rb+1, [table - 0], rb-1
+            mul #-2, [table+1], [table]
+            hlt
+            table: data 5, 7, table
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            program,
+            vec![109, 11, 20201, 1, 11, -1, 102, -2, 12, 11, 99, 5, 7, 11]
+        );
+    }
+
+    #[test]
+    fn round_trip_listing() {
+        let program = Intcode::from_file("inputs/day05.txt").unwrap();
+        let listing = disassemble::listing(program.memory());
+        let words: Vec<i64> = (0..program.memory().len())
+            .map(|address| program.memory().get(address))
+            .collect();
+
+        assert_eq!(assemble(&listing).unwrap(), words);
+    }
+
+    #[test]
+    fn round_trip_odd_words() {
+        // An immediate target, unused mode digits, a truncated instruction
+        // and an unknown opcode.
+        let words = vec![
+            11101, 1, 2, 3, 1099, 10104, 5, 99, 1002, 4, 3, 4, 42, 1101, 7,
+        ];
+        let listing = disassemble::listing(&Memory::new(words.clone()));
+
+        assert_eq!(assemble(&listing).unwrap(), words);
+    }
+
+    #[test]
+    fn custom_operations() {
+        assert_eq!(
+            assemble("op42 [1], #2\nop20").unwrap(),
+            vec![1042, 1, 2, 20]
+        );
+        assert_eq!(
+            assemble("op99 #1").unwrap_err().kind,
+            AssembleErrorKind::UnknownMnemonic("op99".to_string())
+        );
+        assert_eq!(
+            assemble("op7").unwrap_err().kind,
+            AssembleErrorKind::UnknownMnemonic("op7".to_string())
+        );
+    }
+
+    #[test]
+    fn diagnostics() {
+        let error = |source| assemble(source).unwrap_err();
+
+        assert_eq!(
+            error("hlt\njmp #0"),
+            AssembleError {
+                line: 2,
+                kind: AssembleErrorKind::UnknownMnemonic("jmp".to_string())
+            }
+        );
+        assert_eq!(
+            error("out [end]").kind,
+            AssembleErrorKind::UnresolvedLabel("end".to_string())
+        );
+        assert_eq!(
+            error("add #1, #2").kind,
+            AssembleErrorKind::WrongOperandCount {
+                mnemonic: "add".to_string(),
+                expected: 3,
+                found: 2
+            }
+        );
+        assert_eq!(
+            error("out [1").kind,
+            AssembleErrorKind::MalformedOperand("[1".to_string())
+        );
+        assert_eq!(
+            error("out rb*2").kind,
+            AssembleErrorKind::MalformedOperand("rb*2".to_string())
+        );
+        assert_eq!(
+            error("in #5").kind,
+            AssembleErrorKind::ImmediateTarget("#5".to_string())
+        );
+        assert_eq!(
+            error("a: hlt\na: hlt").kind,
+            AssembleErrorKind::DuplicateLabel("a".to_string())
+        );
+        assert_eq!(
+            error("1: hlt").kind,
+            AssembleErrorKind::AddressMismatch {
+                expected: 1,
+                actual: 0
+            }
+        );
+        assert_eq!(
+            error("hlt\nx: out [x+9223372036854775807]").kind,
+            AssembleErrorKind::Overflow("x+9223372036854775807".to_string())
+        );
+        assert_eq!(
+            error("out [1]\n2 x: hlt").to_string(),
+            "line 2: malformed label `2 x`"
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use super::instruction::Decoded;
+use super::Word;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Instructions at or above this address are not cached, so jumping far away
+/// does not grow the cache to gigabytes.
+pub(super) const CACHE_LIMIT: usize = 1 << 16;
+
+/// Slots below this value hold the index of a cached instruction. Once
+/// invalidated, an index is marked with this bit, and reused when the address
+/// is cached again.
+const STALE: u32 = 1 << 31;
+
+/// The slot of an address that was never cached.
+const UNASSIGNED: u32 = u32::MAX;
+
+/// Decoded instructions by the address they start at.
+///
+/// An instruction is only cached the second time it is executed, so programs
+/// that run most of their code once, like the diagnostics of day 5, do not
+/// pay for filling the cache.
+///
+/// Clones share the entries until one of them caches a new instruction or
+/// invalidates one, so cloning a machine with a warm cache is cheap.
+#[derive(Clone)]
+pub(super) struct DecodeCache<W> {
+    entries: Arc<Entries<W>>,
+    /// A bit for every address that was executed. It is not shared, since it
+    /// changes on every new address, but clones copy just a bit per address.
+    seen: Vec<u64>,
+    /// The addresses covered by the instructions cached so far. Most writes
+    /// go to data outside of it, and need no further checks.
+    code: Range<usize>,
+}
+
+#[derive(Clone)]
+struct Entries<W> {
+    /// For every address up to the highest one cached so far, the index of
+    /// its instruction in `decoded`, or `UNASSIGNED`.
+    slots: Vec<u32>,
+    decoded: Vec<Decoded<W>>,
+    /// The number of words of the longest instruction cached so far. Writes
+    /// invalidate the instructions up to this many words before them.
+    longest: usize,
+}
+
+impl<W: Word> DecodeCache<W> {
+    /// Creates an empty cache for a program of `len` words.
+    pub(super) fn new(len: usize) -> DecodeCache<W> {
+        DecodeCache {
+            entries: Arc::new(Entries {
+                slots: vec![],
+                decoded: vec![],
+                longest: 1,
+            }),
+            seen: vec![0; len.min(CACHE_LIMIT) / 64 + 1],
+            code: 0..0,
+        }
+    }
+
+    /// Returns the instruction at `address`, if it is cached.
+    #[inline]
+    pub(super) fn get(&self, address: usize) -> Option<&Decoded<W>> {
+        match self.entries.slots.get(address) {
+            Some(&slot) if slot < STALE => Some(&self.entries.decoded[slot as usize]),
+            _ => None,
+        }
+    }
+
+    /// Records that the instruction at `address`, which must be below
+    /// `CACHE_LIMIT`, is executed without being cached. Returns whether it
+    /// was executed before, and should be cached now.
+    pub(super) fn admit(&mut self, address: usize) -> bool {
+        debug_assert!(address < CACHE_LIMIT);
+
+        let (index, bit) = (address / 64, 1 << (address % 64));
+        if index >= self.seen.len() {
+            self.seen.resize(index + 1, 0);
+        }
+
+        let seen = self.seen[index] & bit != 0;
+        self.seen[index] |= bit;
+        seen
+    }
+
+    /// Caches the instruction at `address`, which was admitted before.
+    pub(super) fn insert(&mut self, address: usize, decoded: Decoded<W>) -> &Decoded<W> {
+        let end = address + decoded.size();
+        self.code = match self.code.is_empty() {
+            true => address..end,
+            false => self.code.start.min(address)..self.code.end.max(end),
+        };
+
+        let entries = Arc::make_mut(&mut self.entries);
+        entries.longest = entries.longest.max(decoded.size());
+
+        if address >= entries.slots.len() {
+            entries.slots.resize(address + 1, UNASSIGNED);
+        }
+        let index = match entries.slots[address] {
+            UNASSIGNED => {
+                entries.decoded.push(decoded);
+                entries.decoded.len() - 1
+            }
+            slot => {
+                let index = (slot & !STALE) as usize;
+                entries.decoded[index] = decoded;
+                index
+            }
+        };
+        entries.slots[address] = index as u32;
+
+        &entries.decoded[index]
+    }
+
+    /// Forgets every instruction that overlaps `address`, after it was written
+    /// to.
+    #[inline]
+    pub(super) fn invalidate(&mut self, address: usize) {
+        if self.code.contains(&address) {
+            self.invalidate_code(address);
+        }
+    }
+
+    #[cold]
+    fn invalidate_code(&mut self, address: usize) {
+        let first = address.saturating_sub(self.entries.longest - 1);
+        let end = (address + 1).min(self.entries.slots.len());
+        if !self.entries.slots[first..end]
+            .iter()
+            .any(|&slot| slot < STALE)
+        {
+            return;
+        }
+
+        for slot in &mut Arc::make_mut(&mut self.entries).slots[first..end] {
+            if *slot < STALE {
+                *slot |= STALE;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Intcode;
+
+    #[test]
+    fn clones_share_entries() {
+        // Counts down from its input and outputs every value.
+        let mut machine = Intcode::new(vec![3, 12, 4, 12, 1001, 12, -1, 12, 1005, 12, 2, 99, 0]);
+        machine.enable_decode_cache();
+        assert_eq!(machine.clone().run(vec![2]).unwrap(), vec![2, 1]);
+
+        let mut warm = machine.clone();
+        warm.run(vec![3]).unwrap();
+        let shared = warm.clone();
+        assert!(std::sync::Arc::ptr_eq(
+            &warm.cache.as_ref().unwrap().entries,
+            &shared.cache.as_ref().unwrap().entries
+        ));
+
+        // Rewriting the program only changes the copy that was patched.
+        warm.patch(4, 1101);
+        assert!(!std::sync::Arc::ptr_eq(
+            &warm.cache.as_ref().unwrap().entries,
+            &shared.cache.as_ref().unwrap().entries
+        ));
+    }
+
+    #[test]
+    fn long_custom_instructions() {
+        use super::super::isa::{Action, InstructionSet, ParameterKind};
+        use super::super::Status;
+
+        // Outputs the sum of its five parameters.
+        let mut isa = InstructionSet::full();
+        isa.register(42, &[ParameterKind::Read; 5], |values| {
+            Action::Output(values.iter().sum())
+        })
+        .unwrap();
+
+        // Outputs 1+2+3+4+5, then patches the last parameter and loops.
+        let mut machine = Intcode::new(vec![1111142, 1, 2, 3, 4, 5, 1101, 0, 10, 5, 1105, 1, 0]);
+        machine.set_instruction_set(isa);
+        machine.enable_decode_cache();
+        machine.set_limits(super::super::Limits {
+            outputs: Some(2),
+            ..Default::default()
+        });
+
+        let mut outputs = vec![];
+        while let Ok(Status::Output(value)) = machine.resume() {
+            outputs.push(value);
+        }
+        assert_eq!(outputs, vec![15, 20]);
+    }
+}
@@ -0,0 +1,568 @@
+//! Drawing the output of programs that paint on a grid.
+//!
+//! Many programs emit `(x, y, tile)` triples. A `Canvas` collects them into a
+//! sparse grid, either as the output sink of `Intcode::run_with` or from the
+//! outputs of `run`. Programs that paint differently, like the robot of day
+//! 11 with its `(color, turn)` pairs, get a `Mapping` of their own. The grid
+//! is shown through a viewport, as text with the glyphs of a `Palette` or as
+//! a PPM image with its colors.
+
+use super::OutputSink;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+/// An RGB color.
+pub type Color = [u8; 3];
+
+/// Colors for the first few tiles: black, white and then a few that are easy
+/// to tell apart.
+const COLORS: [Color; 8] = [
+    [0, 0, 0],
+    [255, 255, 255],
+    [230, 60, 50],
+    [60, 180, 75],
+    [40, 110, 220],
+    [240, 200, 40],
+    [145, 80, 190],
+    [70, 200, 210],
+];
+
+/// The most tiles `render` shows and the most pixels `ppm` draws, so a
+/// viewport over tiles that are far apart does not take all the memory.
+pub const MAX_AREA: usize = 1 << 24;
+
+#[derive(Debug, PartialEq)]
+pub enum CanvasError {
+    /// The viewport reaches beyond the coordinates a tile can have.
+    OutOfRange,
+    /// The viewport has more than `MAX_AREA` tiles or pixels.
+    TooLarge,
+}
+
+/// Groups a stream of outputs into tuples of a fixed size. Tuples of size 0
+/// are never complete, so all outputs are dropped.
+#[derive(Clone, Debug)]
+pub struct Tuples {
+    size: usize,
+    pending: Vec<i64>,
+}
+
+impl Tuples {
+    pub fn new(size: usize) -> Tuples {
+        Tuples {
+            size,
+            pending: Vec::with_capacity(size),
+        }
+    }
+
+    /// Adds an output, and returns the tuple it completes.
+    pub fn push(&mut self, value: i64) -> Option<Vec<i64>> {
+        if self.size == 0 {
+            return None;
+        }
+
+        self.pending.push(value);
+        if self.pending.len() == self.size {
+            Some(std::mem::replace(
+                &mut self.pending,
+                Vec::with_capacity(self.size),
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// The outputs of the tuple that is not complete yet.
+    pub fn pending(&self) -> &[i64] {
+        &self.pending
+    }
+}
+
+/// Turns tuples of outputs into the tiles they draw.
+pub trait Mapping {
+    /// The number of outputs in a tuple. With 0, nothing is drawn.
+    fn tuple_size(&self) -> usize;
+
+    /// The position and tile a complete tuple draws, if any.
+    fn map(&mut self, tuple: &[i64]) -> Option<((i64, i64), i64)>;
+}
+
+/// The mapping of programs that emit `(x, y, tile)` triples.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Triples;
+
+impl Mapping for Triples {
+    fn tuple_size(&self) -> usize {
+        3
+    }
+
+    fn map(&mut self, tuple: &[i64]) -> Option<((i64, i64), i64)> {
+        Some(((tuple[0], tuple[1]), tuple[2]))
+    }
+}
+
+/// How tiles are shown. Tiles without a glyph or color of their own use the
+/// fallback.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub glyphs: HashMap<i64, char>,
+    pub colors: HashMap<i64, Color>,
+    pub fallback_glyph: char,
+    pub fallback_color: Color,
+}
+
+impl Palette {
+    /// A palette that shows tile `i` as the `i`th character of `glyphs`.
+    /// The first few tiles get distinct colors, starting with black and
+    /// white.
+    pub fn new(glyphs: &str) -> Palette {
+        Palette {
+            glyphs: (0..).zip(glyphs.chars()).collect(),
+            colors: (0..).zip(COLORS.iter().cloned()).collect(),
+            fallback_glyph: '?',
+            fallback_color: [255, 0, 255],
+        }
+    }
+
+    pub fn glyph(&self, tile: i64) -> char {
+        self.glyphs
+            .get(&tile)
+            .cloned()
+            .unwrap_or(self.fallback_glyph)
+    }
+
+    pub fn color(&self, tile: i64) -> Color {
+        self.colors
+            .get(&tile)
+            .cloned()
+            .unwrap_or(self.fallback_color)
+    }
+}
+
+/// Black and white, for programs that paint panels.
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::new(" #")
+    }
+}
+
+/// The rectangle of the grid that is shown. `y` grows downwards.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub left: i64,
+    pub top: i64,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Viewport {
+    /// The size of the viewport with every tile drawn as a square of `scale`
+    /// pixels. Fails unless there are at most `MAX_AREA` pixels and every
+    /// tile has coordinates, which also keeps `rows` and `columns` from
+    /// overflowing.
+    fn pixels(&self, scale: usize) -> Result<(usize, usize), CanvasError> {
+        let pixels = match (
+            self.width.checked_mul(scale),
+            self.height.checked_mul(scale),
+        ) {
+            (Some(width), Some(height))
+                if width
+                    .max(1)
+                    .checked_mul(height.max(1))
+                    .is_some_and(|area| area <= MAX_AREA) =>
+            {
+                (width, height)
+            }
+            _ => return Err(CanvasError::TooLarge),
+        };
+
+        if fits(self.left, self.width) && fits(self.top, self.height) {
+            Ok(pixels)
+        } else {
+            Err(CanvasError::OutOfRange)
+        }
+    }
+
+    fn rows(&self) -> impl Iterator<Item = i64> {
+        let top = self.top;
+        (0..self.height).map(move |dy| top + dy as i64)
+    }
+
+    fn columns(&self) -> impl Iterator<Item = i64> {
+        let left = self.left;
+        (0..self.width).map(move |dx| left + dx as i64)
+    }
+}
+
+/// Whether `len` coordinates starting at `start` all fit into an `i64`.
+fn fits(start: i64, len: usize) -> bool {
+    match len.checked_sub(1) {
+        Some(last) => i64::try_from(last)
+            .ok()
+            .and_then(|last| start.checked_add(last))
+            .is_some(),
+        None => true,
+    }
+}
+
+/// The number of coordinates from `min` to `max`. It only saturates for the
+/// whole range of `i64`, which is one more than `usize` holds.
+fn span(min: i64, max: i64) -> usize {
+    usize::try_from(i128::from(max) - i128::from(min) + 1).unwrap_or(usize::MAX)
+}
+
+/// A sparse grid of tiles. Tiles that were never drawn are 0.
+#[derive(Clone, Debug)]
+pub struct Canvas<M = Triples> {
+    tiles: HashMap<(i64, i64), i64>,
+    /// The smallest and largest coordinates drawn so far.
+    bounds: Option<((i64, i64), (i64, i64))>,
+    /// The viewport, or `None` if it follows the bounds.
+    viewport: Option<Viewport>,
+    tuples: Tuples,
+    mapping: M,
+}
+
+impl Canvas {
+    /// A canvas for programs that emit `(x, y, tile)` triples.
+    pub fn new() -> Canvas {
+        Canvas::with_mapping(Triples)
+    }
+}
+
+impl<M: Mapping> Canvas<M> {
+    /// A canvas that draws the tuples of outputs with `mapping`.
+    pub fn with_mapping(mapping: M) -> Canvas<M> {
+        Canvas {
+            tiles: HashMap::new(),
+            bounds: None,
+            viewport: None,
+            tuples: Tuples::new(mapping.tuple_size()),
+            mapping,
+        }
+    }
+
+    pub fn mapping(&self) -> &M {
+        &self.mapping
+    }
+
+    pub fn get(&self, x: i64, y: i64) -> i64 {
+        self.tiles.get(&(x, y)).cloned().unwrap_or(0)
+    }
+
+    pub fn set(&mut self, x: i64, y: i64, tile: i64) {
+        self.tiles.insert((x, y), tile);
+        self.bounds = Some(match self.bounds {
+            Some(((min_x, min_y), (max_x, max_y))) => {
+                ((min_x.min(x), min_y.min(y)), (max_x.max(x), max_y.max(y)))
+            }
+            None => ((x, y), (x, y)),
+        });
+    }
+
+    /// Draws every tuple of a list of outputs. An incomplete tuple at the end
+    /// is kept until the next outputs complete it.
+    pub fn draw(&mut self, outputs: &[i64]) {
+        for output in outputs {
+            self.draw_output(*output);
+        }
+    }
+
+    fn draw_output(&mut self, output: i64) {
+        if let Some(tuple) = self.tuples.push(output) {
+            if let Some(((x, y), tile)) = self.mapping.map(&tuple) {
+                self.set(x, y, tile);
+            }
+        }
+    }
+
+    /// The coordinates and tiles that were drawn, in no particular order.
+    pub fn tiles(&self) -> impl Iterator<Item = ((i64, i64), i64)> + '_ {
+        self.tiles.iter().map(|(position, tile)| (*position, *tile))
+    }
+
+    /// The smallest viewport that shows every tile drawn so far.
+    pub fn bounds(&self) -> Viewport {
+        match self.bounds {
+            Some(((min_x, min_y), (max_x, max_y))) => Viewport {
+                left: min_x,
+                top: min_y,
+                width: span(min_x, max_x),
+                height: span(min_y, max_y),
+            },
+            None => Viewport {
+                left: 0,
+                top: 0,
+                width: 0,
+                height: 0,
+            },
+        }
+    }
+
+    pub fn viewport(&self) -> Viewport {
+        self.viewport.unwrap_or_else(|| self.bounds())
+    }
+
+    /// Fixes the viewport, or makes it follow the bounds again with `None`.
+    pub fn set_viewport(&mut self, viewport: Option<Viewport>) {
+        self.viewport = viewport;
+    }
+
+    /// Moves the viewport, which fixes it if it followed the bounds. It stops
+    /// at the edge of the coordinates.
+    pub fn pan(&mut self, dx: i64, dy: i64) {
+        let viewport = self.viewport();
+        self.viewport = Some(Viewport {
+            left: viewport.left.saturating_add(dx),
+            top: viewport.top.saturating_add(dy),
+            ..viewport
+        });
+    }
+
+    /// The viewport as text, one line per row.
+    pub fn render(&self, palette: &Palette) -> Result<String, CanvasError> {
+        let viewport = self.viewport();
+        viewport.pixels(1)?;
+        let mut text = String::new();
+
+        for y in viewport.rows() {
+            text.extend(viewport.columns().map(|x| palette.glyph(self.get(x, y))));
+            text.push('\n');
+        }
+        Ok(text)
+    }
+
+    /// The viewport as a binary PPM image, with every tile drawn as a square
+    /// of `scale` pixels.
+    pub fn ppm(&self, palette: &Palette, scale: usize) -> Result<Vec<u8>, CanvasError> {
+        let viewport = self.viewport();
+        let (width, height) = viewport.pixels(scale)?;
+        let mut image = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+
+        for y in viewport.rows() {
+            let row: Vec<u8> = viewport
+                .columns()
+                .flat_map(|x| {
+                    let color = palette.color(self.get(x, y));
+                    std::iter::repeat_n(color, scale).flatten()
+                })
+                .collect();
+            for _ in 0..scale {
+                image.extend(&row);
+            }
+        }
+        Ok(image)
+    }
+}
+
+impl<M: Mapping + Default> Default for Canvas<M> {
+    fn default() -> Canvas<M> {
+        Canvas::with_mapping(M::default())
+    }
+}
+
+impl<M: Mapping> OutputSink<i64> for Canvas<M> {
+    fn write_output(&mut self, value: i64) -> Result<(), i64> {
+        self.draw_output(value);
+        Ok(())
+    }
+}
+
+impl fmt::Display for CanvasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CanvasError::OutOfRange => write!(f, "Viewport reaches beyond the grid"),
+            CanvasError::TooLarge => {
+                write!(f, "Viewport has more than {} tiles or pixels", MAX_AREA)
+            }
+        }
+    }
+}
+
+impl Error for CanvasError {}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Intcode, IterInput};
+    use super::*;
+
+    #[test]
+    fn tuples() {
+        let mut tuples = Tuples::new(2);
+
+        assert_eq!(tuples.push(1), None);
+        assert_eq!(tuples.pending(), &[1]);
+        assert_eq!(tuples.push(2), Some(vec![1, 2]));
+        assert!(tuples.pending().is_empty());
+
+        let mut empty = Tuples::new(0);
+        assert_eq!(empty.push(1), None);
+        assert!(empty.pending().is_empty());
+    }
+
+    #[test]
+    fn draw_triples() {
+        let mut canvas = Canvas::new();
+        canvas.draw(&[1, 2, 3, 6, 5]);
+        assert_eq!(canvas.get(1, 2), 3);
+        assert_eq!(canvas.tiles().count(), 1);
+
+        canvas.draw(&[4, -1, 0, 2]);
+        assert_eq!(canvas.get(6, 5), 4);
+        assert_eq!(canvas.get(-1, 0), 2);
+        assert_eq!(canvas.get(100, 100), 0);
+        assert_eq!(
+            canvas.bounds(),
+            Viewport {
+                left: -1,
+                top: 0,
+                width: 8,
+                height: 6
+            }
+        );
+    }
+
+    #[test]
+    fn render_viewport() {
+        let mut canvas = Canvas::new();
+        canvas.draw(&[0, 0, 1, 2, 0, 2, 1, 1, 1, 3, 1, 7]);
+        let palette = Palette::new(" #.");
+
+        assert_eq!(canvas.render(&palette).unwrap(), "# . \n # ?\n");
+
+        canvas.set_viewport(Some(Viewport {
+            left: 1,
+            top: 0,
+            width: 2,
+            height: 3,
+        }));
+        assert_eq!(canvas.render(&palette).unwrap(), " .\n# \n  \n");
+
+        canvas.set_viewport(None);
+        canvas.pan(-1, 1);
+        assert_eq!(canvas.render(&palette).unwrap(), "  # \n    \n");
+    }
+
+    #[test]
+    fn ppm() {
+        let mut canvas = Canvas::new();
+        canvas.draw(&[0, 0, 1, 1, 0, 0]);
+        let image = canvas.ppm(&Palette::default(), 2).unwrap();
+
+        let header = b"P6\n4 2\n255\n";
+        assert_eq!(&image[..header.len()], header);
+
+        let white = [255; 6];
+        let black = [0; 6];
+        let row = [&white[..], &black[..]].concat();
+        assert_eq!(&image[header.len()..], &[&row[..], &row[..]].concat()[..]);
+    }
+
+    #[test]
+    fn far_apart_tiles() {
+        let mut canvas = Canvas::new();
+        canvas.draw(&[i64::MIN, 0, 1, i64::MAX, 0, 1]);
+        assert_eq!(canvas.bounds().width, usize::MAX);
+        assert_eq!(
+            canvas.render(&Palette::default()),
+            Err(CanvasError::TooLarge)
+        );
+
+        canvas.set_viewport(Some(Viewport {
+            left: i64::MAX - 1,
+            top: 0,
+            width: 2,
+            height: 1,
+        }));
+        assert_eq!(canvas.render(&Palette::default()).unwrap(), " #\n");
+        assert_eq!(
+            canvas.ppm(&Palette::default(), usize::MAX),
+            Err(CanvasError::TooLarge)
+        );
+
+        canvas.pan(1, 0);
+        assert_eq!(canvas.viewport().left, i64::MAX);
+        assert_eq!(
+            canvas.render(&Palette::default()),
+            Err(CanvasError::OutOfRange)
+        );
+    }
+
+    /// Paints like the robot of day 11: every `(color, turn)` pair paints
+    /// the panel under it, then turns left on 0 or right on 1 and moves on.
+    struct Robot {
+        position: (i64, i64),
+        direction: (i64, i64),
+    }
+
+    impl Mapping for Robot {
+        fn tuple_size(&self) -> usize {
+            2
+        }
+
+        fn map(&mut self, tuple: &[i64]) -> Option<((i64, i64), i64)> {
+            let painted = self.position;
+            let (dx, dy) = self.direction;
+            self.direction = if tuple[1] == 0 { (dy, -dx) } else { (-dy, dx) };
+            self.position = (
+                self.position.0 + self.direction.0,
+                self.position.1 + self.direction.1,
+            );
+            Some((painted, tuple[0]))
+        }
+    }
+
+    #[test]
+    fn pairs() {
+        let mut canvas = Canvas::with_mapping(Robot {
+            position: (0, 0),
+            direction: (0, -1),
+        });
+        canvas.draw(&[1, 0, 0, 0, 1, 0, 1, 0, 0, 1, 1, 0, 1, 0]);
+
+        assert_eq!(canvas.mapping().position, (0, -1));
+        assert_eq!(
+            canvas.render(&Palette::default()).unwrap(),
+            "  #\n  #\n## \n"
+        );
+    }
+
+    #[test]
+    fn empty_tuples() {
+        struct Nothing;
+
+        impl Mapping for Nothing {
+            fn tuple_size(&self) -> usize {
+                0
+            }
+
+            fn map(&mut self, _: &[i64]) -> Option<((i64, i64), i64)> {
+                Some(((0, 0), 1))
+            }
+        }
+
+        let mut canvas = Canvas::with_mapping(Nothing);
+        canvas.draw(&[1, 2, 3]);
+        assert_eq!(canvas.tiles().count(), 0);
+        assert_eq!(canvas.render(&Palette::default()).unwrap(), "");
+    }
+
+    #[test]
+    fn output_sink() {
+        // Draws a diagonal of tiles 1 to 3.
+        let mut program = Intcode::new(vec![
+            104, 0, 104, 0, 104, 1, 104, 1, 104, 1, 104, 2, 104, 2, 104, 2, 104, 3, 99,
+        ]);
+        let mut canvas = Canvas::new();
+        program
+            .run_with(&mut IterInput(std::iter::empty()), &mut canvas)
+            .unwrap();
+
+        assert_eq!(
+            canvas.render(&Palette::new(".abc")).unwrap(),
+            "a..\n.b.\n..c\n"
+        );
+    }
+}
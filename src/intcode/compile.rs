@@ -0,0 +1,905 @@
+//! Ahead-of-time compilation of Intcode programs into closures.
+//!
+//! Every instruction the compiler finds, following the control-flow graph
+//! and a linear sweep over the image, becomes a closure with its addressing
+//! modes resolved at compile time. Running a compiled program skips decoding
+//! entirely and keeps memory in a flat vector, which makes it several times
+//! faster than the interpreter.
+//!
+//! Writes to compiled code are tracked: the affected instructions are
+//! compiled again from memory when they run next, in the machine that wrote
+//! them. Code that cannot be compiled, like invalid instructions or jumps
+//! past the program image, and every instruction that fails hands the
+//! machine over to the interpreter, which then reports the error exactly
+//! like a plain `Intcode` would.
+//!
+//! Compiled machines keep to the limits of the program they were compiled
+//! from, see `Limits`. Once a limit is reached, they hand over to the
+//! interpreter as well, which reports it. Detecting infinite loops needs the
+//! state of every instruction, so machines with cycle detection enabled run
+//! in the interpreter from the start.
+
+use super::disassemble::{disassemble, Line};
+use super::flow::FlowGraph;
+use super::limits::{Budget, Limits};
+use super::{Instruction, Intcode, IntcodeError, Memory, Operation, Parameter, Status};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+/// Memory grows up to this many words before a machine falls back to the
+/// interpreter, which stores far addresses sparsely.
+const MEMORY_LIMIT: usize = 1 << 20;
+
+/// The number of words of the longest instruction.
+const MAX_INSTRUCTION_SIZE: usize = 4;
+
+type Op = Box<dyn Fn(&mut State) -> Flow + Send + Sync>;
+
+/// What a compiled instruction asks its machine to do next.
+enum Flow {
+    Continue(usize),
+    Output(i64, usize),
+    NeedsInput,
+    Halt,
+    /// The instruction at this address cannot run compiled. It has no effects
+    /// yet, unless it is not the current instruction.
+    Interpret(usize),
+}
+
+/// Which code runs at an address.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Slot {
+    /// The instruction compiled ahead of time, if there is one.
+    Shared,
+    /// Memory was written, so the instruction has to be compiled again.
+    Stale,
+    /// The instruction this machine compiled itself.
+    Local,
+}
+
+/// A program compiled ahead of time. Machines created from it share its
+/// code, so it is cheap to run many of them.
+#[derive(Clone)]
+pub struct Compiled {
+    code: Arc<Code>,
+}
+
+struct Code {
+    ops: Vec<Option<Op>>,
+    /// Whether a word belongs to a compiled instruction.
+    covered: Vec<bool>,
+    /// The machine the program was compiled from.
+    program: Intcode,
+}
+
+/// A machine that runs a compiled program, with the same interface as
+/// `Intcode`.
+#[derive(Clone)]
+pub struct CompiledMachine {
+    code: Arc<Code>,
+    position: usize,
+    state: State,
+    local: HashMap<usize, Arc<Op>>,
+    budget: Option<Box<Budget>>,
+    /// The outputs of a run that stopped at a limit.
+    outputs: Vec<i64>,
+    /// The interpreter the machine handed over to, if any.
+    interpreter: Option<Intcode>,
+}
+
+#[derive(Clone)]
+struct State {
+    memory: Vec<i64>,
+    relative_base: i64,
+    inputs: VecDeque<i64>,
+    /// Writes must go below this address, or the machine hands over to the
+    /// interpreter. It is the lower of `MEMORY_LIMIT` and the memory limit of
+    /// the machine.
+    memory_limit: usize,
+    /// Whether a word belongs to an instruction that may run compiled.
+    covered: Vec<bool>,
+    slots: Vec<Slot>,
+}
+
+impl Compiled {
+    /// Compiles the memory image of a machine. Machines created from the
+    /// result start in the state of `program`.
+    pub fn new(program: &Intcode) -> Compiled {
+        let memory = program.memory();
+        let mut instructions: BTreeMap<usize, Instruction> = BTreeMap::new();
+
+        if memory.len() <= MEMORY_LIMIT {
+            for block in FlowGraph::build(memory).blocks.into_values() {
+                instructions.extend(block.instructions);
+            }
+            for line in disassemble(memory) {
+                if let Line::Code {
+                    address,
+                    instruction,
+                } = line
+                {
+                    instructions.entry(address).or_insert(instruction);
+                }
+            }
+        }
+
+        let len = memory.len().min(MEMORY_LIMIT);
+        let mut ops: Vec<Option<Op>> = (0..len).map(|_| None).collect();
+        let mut covered = vec![false; len];
+        for (address, instruction) in &instructions {
+            if let Some(op) = compile_for(program, *address, instruction) {
+                ops[*address] = Some(op);
+                let end = (address + instruction.size()).min(len);
+                for word in &mut covered[*address..end] {
+                    *word = true;
+                }
+            }
+        }
+
+        Compiled {
+            code: Arc::new(Code {
+                ops,
+                covered,
+                program: program.clone(),
+            }),
+        }
+    }
+
+    /// Creates a machine that runs the program from the start. Its limits
+    /// count from zero, whatever the program used so far.
+    pub fn machine(&self) -> CompiledMachine {
+        let program = &self.code.program;
+        let memory = program.memory();
+
+        let mut budget = program.budget.clone();
+        if let Some(budget) = &mut budget {
+            budget.restart();
+        }
+        let interpreter = if memory.len() > MEMORY_LIMIT || program.cycles.is_some() {
+            let mut machine = program.clone();
+            machine.budget = budget.clone();
+            Some(machine)
+        } else {
+            None
+        };
+        let memory = if interpreter.is_none() {
+            (0..memory.len())
+                .map(|address| memory.get(address))
+                .collect()
+        } else {
+            vec![]
+        };
+
+        CompiledMachine {
+            code: self.code.clone(),
+            position: program.position(),
+            state: State {
+                memory,
+                relative_base: *program.relative_base(),
+                inputs: program.inputs.clone(),
+                memory_limit: memory_limit(program.limits()),
+                covered: self.code.covered.clone(),
+                slots: vec![Slot::Shared; self.code.ops.len()],
+            },
+            local: HashMap::new(),
+            budget,
+            outputs: vec![],
+            interpreter,
+        }
+    }
+
+    /// Runs a machine with some words of memory overwritten, and returns the
+    /// word at address 0 once it halts, like `Intcode::run_patched`.
+    pub fn run_patched(&self, patches: &[(usize, i64)]) -> Result<i64, IntcodeError> {
+        let mut machine = self.machine();
+        for (address, value) in patches {
+            machine.patch(*address, *value);
+        }
+
+        machine.run(vec![])?;
+        Ok(machine.get(0))
+    }
+}
+
+impl CompiledMachine {
+    /// The instruction pointer.
+    pub fn position(&self) -> usize {
+        match &self.interpreter {
+            Some(machine) => machine.position(),
+            None => self.position,
+        }
+    }
+
+    /// Reads a word of memory.
+    pub fn get(&self, address: usize) -> i64 {
+        match &self.interpreter {
+            Some(machine) => machine.memory().get(address),
+            None => self.state.load(address),
+        }
+    }
+
+    /// Whether the machine handed over to the interpreter.
+    pub fn is_interpreted(&self) -> bool {
+        self.interpreter.is_some()
+    }
+
+    /// Overwrites a word of memory, e.g. to set the parameters of a program
+    /// before it runs. Instructions that contain the word are compiled again.
+    pub fn patch(&mut self, address: usize, value: i64) {
+        if self.interpreter.is_none() && !self.state.store(address, value) {
+            self.interpret(self.position);
+        }
+        if let Some(machine) = &mut self.interpreter {
+            machine.patch(address, value);
+        }
+    }
+
+    /// Limits the resources the machine may use from now on, like
+    /// `Intcode::set_limits`.
+    pub fn set_limits(&mut self, limits: Limits) {
+        match &mut self.interpreter {
+            Some(machine) => machine.set_limits(limits),
+            None => {
+                self.state.memory_limit = memory_limit(Some(&limits));
+                self.budget = Some(Box::new(Budget::new(limits)));
+            }
+        }
+    }
+
+    pub fn limits(&self) -> Option<&Limits> {
+        match &self.interpreter {
+            Some(machine) => machine.limits(),
+            None => self.budget.as_ref().map(|budget| &budget.limits),
+        }
+    }
+
+    /// Stops the machine with `IntcodeError::InfiniteLoop` once it repeats a
+    /// state, like `Intcode::enable_cycle_detection`. Compiled code does not
+    /// keep track of its states, so the machine hands over to the interpreter.
+    pub fn enable_cycle_detection(&mut self) {
+        self.interpret(self.position).enable_cycle_detection();
+    }
+
+    /// Queues a value to be consumed by the next input instruction.
+    pub fn provide_input(&mut self, value: i64) {
+        match &mut self.interpreter {
+            Some(machine) => machine.provide_input(value),
+            None => self.state.inputs.push_back(value),
+        }
+    }
+
+    /// Executes instructions until the program produces an output, waits for
+    /// an input or halts, like `Intcode::resume`.
+    pub fn resume(&mut self) -> Result<Status, IntcodeError> {
+        if let Some(machine) = &mut self.interpreter {
+            return machine.resume();
+        }
+
+        loop {
+            let position = self.position;
+            if let Some(budget) = &self.budget {
+                if budget.check().is_err() {
+                    return self.interpret(position).resume();
+                }
+            }
+
+            let flow = match self.state.slots.get(position) {
+                Some(Slot::Shared) => match &self.code.ops[position] {
+                    Some(op) => op(&mut self.state),
+                    None => self.recompile(position),
+                },
+                Some(Slot::Local) => (self.local[&position])(&mut self.state),
+                Some(Slot::Stale) => self.recompile(position),
+                None => Flow::Interpret(position),
+            };
+
+            match flow {
+                Flow::Continue(next) => {
+                    self.position = next;
+                    self.spend(false);
+                }
+                Flow::Output(_, _) if !self.may_output() => {
+                    return self.interpret(position).resume()
+                }
+                Flow::Output(value, next) => {
+                    self.position = next;
+                    self.spend(true);
+                    return Ok(Status::Output(value));
+                }
+                Flow::NeedsInput => return Ok(Status::NeedsInput),
+                Flow::Halt => {
+                    self.spend(false);
+                    return Ok(Status::Halted);
+                }
+                Flow::Interpret(position) => return self.interpret(position).resume(),
+            }
+        }
+    }
+
+    /// Runs the program to completion on the given inputs and collects its
+    /// outputs, like `Intcode::run`. If the machine stops at a limit, the
+    /// outputs it emitted so far are kept for `take_outputs`.
+    pub fn run(&mut self, inputs: Vec<i64>) -> Result<Vec<i64>, IntcodeError> {
+        for input in inputs {
+            self.provide_input(input);
+        }
+
+        let mut outputs = vec![];
+        loop {
+            match self.resume() {
+                Ok(Status::Output(value)) => outputs.push(value),
+                Ok(Status::Halted) => return Ok(outputs),
+                Ok(Status::Running) => {}
+                Ok(Status::NeedsInput) => {
+                    return Err(IntcodeError::MissingInput {
+                        position: self.position(),
+                        opcode: self.get(self.position()),
+                    })
+                }
+                Err(error) => {
+                    if error.is_limit() {
+                        self.outputs.extend(outputs);
+                    }
+                    return Err(error);
+                }
+            }
+        }
+    }
+
+    /// Removes and returns the outputs kept by `run`.
+    pub fn take_outputs(&mut self) -> Vec<i64> {
+        std::mem::take(&mut self.outputs)
+    }
+
+    /// Counts an executed instruction against the limits, if there are any.
+    #[inline]
+    fn spend(&mut self, output: bool) {
+        if let Some(budget) = &mut self.budget {
+            budget.executed += 1;
+            if output {
+                budget.outputs += 1;
+            }
+        }
+    }
+
+    fn may_output(&self) -> bool {
+        self.budget
+            .as_ref()
+            .is_none_or(|budget| budget.may_output())
+    }
+
+    /// Compiles the instruction at `position` from the current memory, and
+    /// runs it if it is valid.
+    fn recompile(&mut self, position: usize) -> Flow {
+        let end = (position + MAX_INSTRUCTION_SIZE).min(self.state.memory.len());
+        let memory = Memory::new(self.state.memory[position..end].to_vec());
+        let op = match Instruction::decode(&memory, 0) {
+            Some(instruction) => match compile_for(&self.code.program, position, &instruction) {
+                Some(op) => {
+                    let end = (position + instruction.size()).min(self.state.covered.len());
+                    for word in &mut self.state.covered[position..end] {
+                        *word = true;
+                    }
+                    op
+                }
+                None => return Flow::Interpret(position),
+            },
+            None => return Flow::Interpret(position),
+        };
+
+        let op = Arc::new(op);
+        self.local.insert(position, op.clone());
+        self.state.slots[position] = Slot::Local;
+        op(&mut self.state)
+    }
+
+    /// Hands the machine over to the interpreter, which continues at
+    /// `position`.
+    fn interpret(&mut self, position: usize) -> &mut Intcode {
+        if self.interpreter.is_none() {
+            let mut machine = Intcode::new(std::mem::take(&mut self.state.memory));
+            machine.program_len = self.code.program.program_len;
+            machine.position = position;
+            machine.relative_base = self.state.relative_base;
+            machine.inputs = std::mem::take(&mut self.state.inputs);
+            machine.isa = self.code.program.isa.clone();
+            machine.budget = self.budget.take();
+            self.interpreter = Some(machine);
+        }
+
+        self.interpreter.as_mut().unwrap()
+    }
+}
+
+impl State {
+    #[inline]
+    fn load(&self, address: usize) -> i64 {
+        self.memory.get(address).copied().unwrap_or(0)
+    }
+
+    /// Writes a word, unless the address is beyond the memory limit.
+    #[inline]
+    fn store(&mut self, address: usize, value: i64) -> bool {
+        if address >= self.memory_limit {
+            return false;
+        }
+        if address >= self.memory.len() {
+            self.memory.resize(address + 1, 0);
+        }
+
+        self.memory[address] = value;
+        if self.covered.get(address) == Some(&true) {
+            self.invalidate(address);
+        }
+        true
+    }
+
+    /// Marks every instruction that may contain `address` as stale.
+    #[cold]
+    fn invalidate(&mut self, address: usize) {
+        let first = address.saturating_sub(MAX_INSTRUCTION_SIZE - 1);
+        for slot in &mut self.slots[first..=address] {
+            *slot = Slot::Stale;
+        }
+    }
+}
+
+/// The address writes of a compiled machine must stay below.
+fn memory_limit(limits: Option<&Limits>) -> usize {
+    limits
+        .and_then(|limits| limits.memory)
+        .map_or(MEMORY_LIMIT, |memory| memory.min(MEMORY_LIMIT))
+}
+
+trait Load: Copy + Send + Sync + 'static {
+    /// The value of the parameter, or `None` if its address is invalid.
+    fn load(self, state: &State) -> Option<i64>;
+}
+
+trait Store: Copy + Send + Sync + 'static {
+    /// The address the parameter writes to, or `None` if it is invalid.
+    fn address(self, state: &State) -> Option<usize>;
+}
+
+#[derive(Clone, Copy)]
+struct Immediate(i64);
+
+#[derive(Clone, Copy)]
+struct Position(usize);
+
+#[derive(Clone, Copy)]
+struct Relative(i64);
+
+impl Load for Immediate {
+    #[inline]
+    fn load(self, _state: &State) -> Option<i64> {
+        Some(self.0)
+    }
+}
+
+impl Load for Position {
+    #[inline]
+    fn load(self, state: &State) -> Option<i64> {
+        Some(state.load(self.0))
+    }
+}
+
+impl Load for Relative {
+    #[inline]
+    fn load(self, state: &State) -> Option<i64> {
+        Some(state.load(self.address(state)?))
+    }
+}
+
+impl Store for Position {
+    #[inline]
+    fn address(self, _state: &State) -> Option<usize> {
+        Some(self.0)
+    }
+}
+
+impl Store for Relative {
+    #[inline]
+    fn address(self, state: &State) -> Option<usize> {
+        let address = state.relative_base.checked_add(self.0)?;
+        usize::try_from(address).ok()
+    }
+}
+
+/// Binds `$name` to the operand of a parameter that is read, with its
+/// addressing mode as its type.
+macro_rules! with_load {
+    ($parameter:expr, |$name:ident| $body:expr) => {
+        match $parameter {
+            Parameter::Position(address) => {
+                let $name = Position(usize::try_from(*address).ok()?);
+                $body
+            }
+            Parameter::Immediate(value) => {
+                let $name = Immediate(*value);
+                $body
+            }
+            Parameter::Relative(offset) => {
+                let $name = Relative(*offset);
+                $body
+            }
+        }
+    };
+}
+
+/// Binds `$name` to the operand of a parameter that is written to.
+macro_rules! with_store {
+    ($parameter:expr, |$name:ident| $body:expr) => {
+        match $parameter {
+            Parameter::Position(address) => {
+                let $name = Position(usize::try_from(*address).ok()?);
+                $body
+            }
+            Parameter::Relative(offset) => {
+                let $name = Relative(*offset);
+                $body
+            }
+            Parameter::Immediate(_) => None,
+        }
+    };
+}
+
+/// Compiles an instruction of `program`, unless its instruction set does not
+/// accept it. Custom operations are never compiled.
+fn compile_for(program: &Intcode, position: usize, instruction: &Instruction) -> Option<Op> {
+    match program.instruction_set() {
+        Some(isa) if !isa.supports(instruction.operation) => None,
+        _ => compile(position, instruction),
+    }
+}
+
+/// Compiles the instruction at `position`, or returns `None` if it would
+/// fail whenever it runs.
+fn compile(position: usize, instruction: &Instruction) -> Option<Op> {
+    let parameters = &instruction.parameters;
+    let next = position + instruction.size();
+
+    match instruction.operation {
+        Operation::Add => binary(position, parameters, i64::checked_add),
+        Operation::Multiply => binary(position, parameters, i64::checked_mul),
+        Operation::LessThan => binary(position, parameters, |a, b| Some((a < b) as i64)),
+        Operation::Equals => binary(position, parameters, |a, b| Some((a == b) as i64)),
+        Operation::Input => with_store!(&parameters[0], |target| Some(Box::new(
+            move |state: &mut State| {
+                let value = match state.inputs.front() {
+                    Some(value) => *value,
+                    None => return Flow::NeedsInput,
+                };
+                match target.address(state) {
+                    Some(address) if state.store(address, value) => {
+                        state.inputs.pop_front();
+                        Flow::Continue(next)
+                    }
+                    _ => Flow::Interpret(position),
+                }
+            }
+        ))),
+        Operation::Output => with_load!(&parameters[0], |value| Some(Box::new(
+            move |state: &mut State| match value.load(state) {
+                Some(value) => Flow::Output(value, next),
+                None => Flow::Interpret(position),
+            }
+        ))),
+        Operation::JumpIfTrue => jump(position, parameters, true),
+        Operation::JumpIfFalse => jump(position, parameters, false),
+        Operation::AdjustRelativeBase => with_load!(&parameters[0], |offset| Some(Box::new(
+            move |state: &mut State| match offset
+                .load(state)
+                .and_then(|offset| state.relative_base.checked_add(offset))
+            {
+                Some(base) => {
+                    state.relative_base = base;
+                    Flow::Continue(next)
+                }
+                None => Flow::Interpret(position),
+            }
+        ))),
+        Operation::Halt => Some(Box::new(|_: &mut State| Flow::Halt)),
+        Operation::Custom { .. } => None,
+    }
+}
+
+fn binary<F>(position: usize, parameters: &[Parameter], f: F) -> Option<Op>
+where
+    F: Fn(i64, i64) -> Option<i64> + Copy + Send + Sync + 'static,
+{
+    with_load!(&parameters[0], |a| with_load!(&parameters[1], |b| {
+        with_store!(&parameters[2], |target| Some(binary_op(
+            position, a, b, target, f
+        )))
+    }))
+}
+
+fn binary_op<A: Load, B: Load, T: Store, F>(position: usize, a: A, b: B, target: T, f: F) -> Op
+where
+    F: Fn(i64, i64) -> Option<i64> + Copy + Send + Sync + 'static,
+{
+    Box::new(move |state: &mut State| {
+        let result = match (a.load(state), b.load(state)) {
+            (Some(a), Some(b)) => f(a, b),
+            _ => None,
+        };
+
+        match (result, target.address(state)) {
+            (Some(value), Some(address)) if state.store(address, value) => {
+                Flow::Continue(position + 4)
+            }
+            _ => Flow::Interpret(position),
+        }
+    })
+}
+
+fn jump(position: usize, parameters: &[Parameter], jump_if: bool) -> Option<Op> {
+    with_load!(&parameters[0], |condition| with_load!(
+        &parameters[1],
+        |target| Some(jump_op(position, condition, target, jump_if))
+    ))
+}
+
+fn jump_op<C: Load, T: Load>(position: usize, condition: C, target: T, jump_if: bool) -> Op {
+    Box::new(
+        move |state: &mut State| match (condition.load(state), target.load(state)) {
+            (Some(condition), _) if (condition != 0) != jump_if => Flow::Continue(position + 3),
+            (Some(_), Some(target)) => match usize::try_from(target) {
+                Ok(target) => Flow::Continue(target),
+                Err(_) => Flow::Interpret(position),
+            },
+            _ => Flow::Interpret(position),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::isa::{Action, InstructionSet, ParameterKind};
+    use super::*;
+
+    /// Runs a program compiled and interpreted, and checks that both agree.
+    fn run_both(program: &Intcode, inputs: Vec<i64>) -> Result<Vec<i64>, IntcodeError> {
+        let compiled = Compiled::new(program).machine().run(inputs.clone());
+        let interpreted = program.clone().run(inputs);
+
+        match (&compiled, &interpreted) {
+            (Ok(compiled), Ok(interpreted)) => assert_eq!(compiled, interpreted),
+            (Err(compiled), Err(interpreted)) => {
+                assert_eq!(compiled.to_string(), interpreted.to_string())
+            }
+            _ => panic!("{:?} != {:?}", compiled, interpreted),
+        }
+
+        compiled
+    }
+
+    #[test]
+    fn diagnostics() -> Result<(), IntcodeError> {
+        let program = Intcode::from_file("inputs/day05.txt")?;
+
+        assert_eq!(run_both(&program, vec![1])?.last(), Some(&5_346_030));
+        assert_eq!(run_both(&program, vec![5])?, vec![513_116]);
+        Ok(())
+    }
+
+    #[test]
+    fn relative_mode_and_large_memory() -> Result<(), IntcodeError> {
+        // Outputs a copy of itself.
+        let quine = vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        assert_eq!(run_both(&Intcode::new(quine.clone()), vec![])?, quine);
+
+        let program = Intcode::new(vec![1102, 34_915_192, 34_915_192, 7, 4, 7, 99, 0]);
+        assert_eq!(run_both(&program, vec![])?, vec![1_219_070_632_396_864]);
+
+        // Writes far beyond the flat memory of a compiled machine.
+        let program = Intcode::new(vec![21101, 3, 4, 5_000_000, 204, 5_000_000, 99]);
+        let mut machine = Compiled::new(&program).machine();
+        assert_eq!(machine.run(vec![])?, vec![7]);
+        assert!(machine.is_interpreted());
+        Ok(())
+    }
+
+    #[test]
+    fn self_modifying_code() -> Result<(), IntcodeError> {
+        // Increments the output instruction's parameter until it is 3.
+        let program = Intcode::new(vec![104, 1, 1001, 1, 1, 1, 1007, 1, 3, 20, 1005, 20, 0, 99]);
+        let mut machine = Compiled::new(&program).machine();
+        assert_eq!(machine.run(vec![])?, vec![1, 2]);
+        assert!(!machine.is_interpreted());
+
+        // Turns the output into a halt.
+        let program = Intcode::new(vec![1101, 0, 99, 4, 104, 7, 99]);
+        assert_eq!(run_both(&program, vec![])?, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn gravity_assist() -> Result<(), IntcodeError> {
+        let program = Intcode::from_file("inputs/day02.txt")?;
+        let compiled = Compiled::new(&program);
+
+        assert_eq!(compiled.run_patched(&[(1, 12), (2, 2)])?, 3_516_593);
+        assert_eq!(
+            compiled.run_patched(&[(1, 77), (2, 49)])?,
+            program.run_patched(&[(1, 77), (2, 49)])?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn amplifier_jump_table() -> Result<(), IntcodeError> {
+        // Jumps through a table indexed by the phase setting.
+        let program = Intcode::from_file("inputs/day07.txt")?;
+
+        for phase in 0..10 {
+            let mut machine = Compiled::new(&program).machine();
+            machine.provide_input(phase);
+            machine.provide_input(1);
+            let mut interpreted = program.clone();
+            interpreted.provide_input(phase);
+            interpreted.provide_input(1);
+
+            assert_eq!(machine.resume()?, interpreted.resume()?);
+            assert!(!machine.is_interpreted());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn instruction_sets() -> Result<(), IntcodeError> {
+        let mut isa = InstructionSet::day05();
+        isa.register(20, &[ParameterKind::Read], |values: &[i64]| {
+            Action::Output(2 * values[0])
+        })
+        .unwrap();
+
+        let mut program = Intcode::new(vec![104, 1, 20, 4, 109, 1, 99]);
+        program.set_instruction_set(isa);
+        let mut machine = Compiled::new(&program).machine();
+
+        assert_eq!(machine.resume()?, Status::Output(1));
+        assert_eq!(machine.resume()?, Status::Output(218));
+        assert!(machine.is_interpreted());
+        assert!(matches!(
+            machine.resume(),
+            Err(IntcodeError::UnknownOperation { position: 4, .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn errors_match_the_interpreter() {
+        let programs = vec![
+            (vec![1105, 1, 9], vec![]),
+            (vec![1101, 1, 1, -1, 99], vec![]),
+            (vec![3, 0, 99], vec![]),
+            (vec![1102, i64::MAX, 2, 0, 99], vec![]),
+            (vec![109, -3, 204, 0, 99], vec![]),
+            (vec![1101, 0, 0, 6], vec![]),
+        ];
+
+        for (program, inputs) in programs {
+            assert!(run_both(&Intcode::new(program), inputs).is_err());
+        }
+    }
+
+    #[test]
+    fn limits() {
+        // Counts up forever, and outputs every count.
+        let mut program = Intcode::new(vec![1001, 9, 1, 9, 4, 9, 1105, 1, 0, 0]);
+        let limits = [
+            Limits {
+                instructions: Some(10),
+                ..Limits::default()
+            },
+            Limits {
+                outputs: Some(3),
+                ..Limits::default()
+            },
+            Limits {
+                memory: Some(9),
+                ..Limits::default()
+            },
+        ];
+
+        for limits in &limits {
+            program.set_limits(limits.clone());
+            let error = run_both(&program, vec![]).unwrap_err();
+            assert!(error.is_limit());
+        }
+
+        let mut machine = Compiled::new(&program).machine();
+        machine.set_limits(Limits {
+            instructions: Some(7),
+            ..Limits::default()
+        });
+        assert_eq!(machine.resume().unwrap(), Status::Output(1));
+        assert_eq!(machine.resume().unwrap(), Status::Output(2));
+        assert!(matches!(
+            machine.resume(),
+            Err(IntcodeError::InstructionLimit {
+                position: 4,
+                executed: 7,
+                ..
+            })
+        ));
+        assert!(machine.is_interpreted());
+        assert_eq!(machine.get(9), 3);
+    }
+
+    #[test]
+    fn limits_of_new_machines() {
+        let mut program = Intcode::new(vec![104, 1, 104, 2, 1105, 1, 4]);
+        program.set_limits(Limits {
+            instructions: Some(100),
+            time: Some(std::time::Duration::from_millis(50)),
+            ..Limits::default()
+        });
+        std::thread::sleep(std::time::Duration::from_millis(100));
+
+        let mut machine = Compiled::new(&program).machine();
+        assert!(matches!(
+            machine.run(vec![]),
+            Err(IntcodeError::InstructionLimit { executed: 100, .. })
+        ));
+        assert_eq!(machine.take_outputs(), vec![1, 2]);
+        assert!(machine.take_outputs().is_empty());
+
+        let program = Intcode::new(vec![104, 1, 99]);
+        let mut limited = program.clone();
+        limited.set_limits(Limits {
+            time: Some(std::time::Duration::from_millis(50)),
+            ..Limits::default()
+        });
+        std::thread::sleep(std::time::Duration::from_millis(100));
+
+        assert_eq!(
+            Compiled::new(&limited).machine().run(vec![]).unwrap(),
+            vec![1]
+        );
+        assert_eq!(Compiled::new(&limited).run_patched(&[]).unwrap(), 104);
+        assert_eq!(limited.run_patched(&[]).unwrap(), 104);
+    }
+
+    #[test]
+    fn cycle_detection() {
+        // Loops forever after reading a zero.
+        let mut program = Intcode::new(vec![3, 9, 1006, 9, 2, 4, 9, 99, 0, 0]);
+        let mut machine = Compiled::new(&program).machine();
+        machine.enable_cycle_detection();
+        assert!(machine.is_interpreted());
+        assert!(matches!(
+            machine.run(vec![0]),
+            Err(IntcodeError::InfiniteLoop {
+                entry: 2,
+                period: 1,
+                ..
+            })
+        ));
+
+        program.enable_cycle_detection();
+        assert!(Compiled::new(&program).machine().is_interpreted());
+        assert!(run_both(&program, vec![0]).is_err());
+        assert_eq!(run_both(&program, vec![3]).unwrap(), vec![3]);
+    }
+
+    #[test]
+    fn resumable_amplifier() {
+        // Adds every input to a running sum, which it outputs.
+        let program = Intcode::new(vec![3, 11, 1, 11, 12, 12, 4, 12, 1105, 1, 0, 0, 0]);
+        let mut machine = Compiled::new(&program).machine();
+
+        assert_eq!(machine.resume().unwrap(), Status::NeedsInput);
+        machine.provide_input(4);
+        assert_eq!(machine.resume().unwrap(), Status::Output(4));
+        machine.provide_input(5);
+        assert_eq!(machine.resume().unwrap(), Status::Output(9));
+        assert_eq!(machine.resume().unwrap(), Status::NeedsInput);
+        assert_eq!(machine.position(), 0);
+    }
+}
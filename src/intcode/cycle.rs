@@ -0,0 +1,289 @@
+//! Detection of programs that loop forever, see
+//! `Intcode::enable_cycle_detection`.
+//!
+//! A machine that does not consume inputs is deterministic, so once it is in a
+//! state it was in before, it repeats the same instructions forever. States
+//! are compared by a hash of the instruction pointer, the relative base and
+//! the memory. The hash of the memory is updated on every write, so it is
+//! cheap to keep. Repeated states are found with Brent's algorithm, which only
+//! keeps a single earlier state around instead of every state seen.
+
+use super::{Intcode, Memory, Word};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// A loop the machine entered.
+pub(super) struct Loop {
+    /// The position of the first instruction of the loop.
+    pub(super) entry: usize,
+    /// The number of instructions in one iteration.
+    pub(super) period: u64,
+}
+
+/// The state of cycle detection. The search starts over whenever the machine
+/// consumes an input or waits for one.
+#[derive(Clone)]
+pub(super) struct Cycles<W> {
+    search: Option<Search<W>>,
+}
+
+#[derive(Clone)]
+struct Search<W> {
+    /// The machine when the search started. It is replayed to find where the
+    /// loop was entered.
+    origin: Intcode<W>,
+    /// The state the following ones are compared with, and its hash.
+    checkpoint: Intcode<W>,
+    hash: u64,
+    /// The number of states after the checkpoint, and how many there may be
+    /// before the checkpoint moves on.
+    length: u64,
+    power: u64,
+}
+
+impl<W> Cycles<W> {
+    pub(super) fn new() -> Cycles<W> {
+        Cycles { search: None }
+    }
+}
+
+fn mix(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+fn word_hash<W: Word>(word: &W) -> u64 {
+    match word.to_i64() {
+        Some(value) => mix(value as u64),
+        None => {
+            let mut hasher = DefaultHasher::new();
+            word.to_string().hash(&mut hasher);
+            mix(hasher.finish())
+        }
+    }
+}
+
+/// The share of a cell in the hash of the memory. Zeros do not count, so
+/// memory that was never written hashes like memory that was cleared.
+pub(super) fn cell_hash<W: Word>(address: usize, value: &W) -> u64 {
+    if value.is_zero() {
+        0
+    } else {
+        mix(word_hash(value) ^ mix(address as u64))
+    }
+}
+
+pub(super) fn memory_hash<W: Word>(memory: &Memory<W>) -> u64 {
+    memory
+        .cells()
+        .fold(0, |hash, (address, value)| hash ^ cell_hash(address, value))
+}
+
+impl<W: Word> Intcode<W> {
+    /// Keeps the hash of the memory up to date before a cell is overwritten.
+    pub(super) fn rehash(&mut self, address: usize, value: &W) {
+        if let Some(hash) = &mut self.memory_hash {
+            *hash ^= cell_hash(address, &self.memory.get(address)) ^ cell_hash(address, value);
+        }
+    }
+
+    fn state_hash(&self) -> u64 {
+        let position = mix(self.position as u64 ^ 0x5bd1_e995);
+        let relative_base = mix(word_hash(&self.relative_base) ^ 0x27d4_eb2f);
+        mix(self.memory_hash.unwrap_or(0) ^ position ^ relative_base)
+    }
+
+    /// Whether two machines are in the same state, as far as the program can
+    /// tell. Only called when the hashes agree, to rule out collisions.
+    fn same_state(&self, other: &Intcode<W>) -> bool {
+        let nonzero = |memory: &'_ Memory<W>| {
+            memory
+                .cells()
+                .filter(|(_, value)| !value.is_zero())
+                .map(|(address, value)| (address, value.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        self.position == other.position
+            && self.relative_base == other.relative_base
+            && nonzero(&self.memory) == nonzero(&other.memory)
+    }
+
+    /// A copy of the program state, without inputs, limits or caches.
+    fn bare_clone(&self) -> Intcode<W> {
+        let mut machine = Intcode::with_words(vec![]);
+        machine.memory = self.memory.clone();
+        machine.position = self.position;
+        machine.relative_base = self.relative_base.clone();
+        machine.isa = self.isa.clone();
+        machine.memory_hash = self.memory_hash;
+        machine
+    }
+
+    /// Looks at the state before the next instruction, and returns the loop
+    /// if the machine was in this state before.
+    pub(super) fn watch_cycles(&mut self) -> Option<Loop> {
+        let mut cycles = self.cycles.take()?;
+        let hash = self.state_hash();
+
+        let found = match &mut cycles.search {
+            Some(search) => search.observe(self, hash),
+            None => {
+                cycles.search = Some(Search {
+                    origin: self.bare_clone(),
+                    checkpoint: self.bare_clone(),
+                    hash,
+                    length: 0,
+                    power: 1,
+                });
+                None
+            }
+        };
+        if found.is_some() {
+            cycles.search = None;
+        }
+
+        self.cycles = Some(cycles);
+        found
+    }
+
+    /// Starts the search over, because the machine consumed an input or
+    /// waits for one.
+    pub(super) fn forget_cycles(&mut self) {
+        if let Some(cycles) = &mut self.cycles {
+            cycles.search = None;
+        }
+    }
+}
+
+impl<W: Word> Search<W> {
+    fn observe(&mut self, machine: &Intcode<W>, hash: u64) -> Option<Loop> {
+        self.length += 1;
+
+        if hash == self.hash && machine.same_state(&self.checkpoint) {
+            return Some(Loop {
+                entry: self.entry(self.length).unwrap_or(machine.position),
+                period: self.length,
+            });
+        }
+
+        if self.length == self.power {
+            self.checkpoint = machine.bare_clone();
+            self.hash = hash;
+            self.power *= 2;
+            self.length = 0;
+        }
+        None
+    }
+
+    /// Replays the machine from the origin to find the first state of the
+    /// loop: one copy runs a period ahead of the other until they meet.
+    fn entry(&self, period: u64) -> Option<usize> {
+        let mut tortoise = self.origin.clone();
+        let mut hare = self.origin.clone();
+        for _ in 0..period {
+            hare.step().ok()?;
+        }
+
+        while tortoise.state_hash() != hare.state_hash() || !tortoise.same_state(&hare) {
+            tortoise.step().ok()?;
+            hare.step().ok()?;
+        }
+        Some(tortoise.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{IntcodeError, Limits, Status};
+    use super::*;
+
+    fn find_loop(program: &mut Intcode) -> Option<(usize, usize, u64)> {
+        program.enable_cycle_detection();
+        match program.run(vec![]) {
+            Err(IntcodeError::InfiniteLoop {
+                position,
+                entry,
+                period,
+                ..
+            }) => Some((position, entry, period)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn jump_to_itself() {
+        let mut program = Intcode::new(vec![1105, 1, 0]);
+        assert_eq!(find_loop(&mut program), Some((0, 0, 1)));
+    }
+
+    #[test]
+    fn entry_after_a_prefix() {
+        // Counts address 16 up to 5, then flips it between 5 and -4 forever.
+        let mut program = Intcode::new(vec![
+            1001, 16, 1, 16, 1007, 16, 5, 17, 1005, 17, 0, 1106, 0, 18, 99, 99, 0, 0, 1002, 16, -1,
+            16, 1001, 16, 1, 16, 1105, 1, 18,
+        ]);
+        let (position, entry, period) = find_loop(&mut program).unwrap();
+        // Toggling takes three instructions, and a full period two toggles.
+        assert_eq!(period, 6);
+        assert_eq!(entry, 18);
+        assert!([18, 22, 26].contains(&position));
+        assert_eq!(program.position(), position);
+    }
+
+    #[test]
+    fn bad_phase_setting() {
+        // An amplifier that echoes its signal for phase 0, and hangs at
+        // position 11 for any other phase.
+        let program = Intcode::new(vec![
+            3, 20, 1005, 20, 11, 3, 21, 4, 21, 99, 99, 1105, 1, 11, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]);
+
+        let mut amplifier = program.clone();
+        amplifier.enable_cycle_detection();
+        assert_eq!(amplifier.run(vec![0, 5]).unwrap(), vec![5]);
+
+        let mut amplifier = program.clone();
+        amplifier.enable_cycle_detection();
+        let error = amplifier.run(vec![1, 5]).unwrap_err();
+        assert!(matches!(
+            error,
+            IntcodeError::InfiniteLoop {
+                position: 11,
+                opcode: 1105,
+                entry: 11,
+                period: 1
+            }
+        ));
+        assert_eq!(error.position(), Some(11));
+        assert!(!error.is_limit());
+    }
+
+    #[test]
+    fn counters_do_not_loop() {
+        let mut program = Intcode::new(vec![1001, 7, 1, 7, 1105, 1, 0, 0]);
+        program.enable_cycle_detection();
+        program.set_limits(Limits {
+            instructions: Some(10_000),
+            ..Limits::default()
+        });
+
+        assert!(program.run(vec![]).unwrap_err().is_limit());
+    }
+
+    #[test]
+    fn inputs_restart_the_search() {
+        // Echoes its inputs forever.
+        let mut program = Intcode::new(vec![3, 9, 4, 9, 1105, 1, 0, 99, 99, 0]);
+        program.enable_cycle_detection();
+
+        for input in &[1, 2, 1, 2, 1] {
+            program.provide_input(*input);
+            assert_eq!(program.resume().unwrap(), Status::Output(*input));
+        }
+        assert_eq!(program.resume().unwrap(), Status::NeedsInput);
+        assert_eq!(program.resume().unwrap(), Status::NeedsInput);
+    }
+}
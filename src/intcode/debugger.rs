@@ -0,0 +1,452 @@
+use super::{Instruction, Intcode, IntcodeError, Status, Word};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+/// The maximum number of instructions `continue` executes before giving
+/// control back, so a program that loops forever does not hang the debugger.
+const CONTINUE_LIMIT: usize = 10_000_000;
+
+pub const HELP: &str = "\
+Commands:
+  s, step [n]          execute one or n instructions
+  c, continue          run until a breakpoint, a watchpoint, an input or halt
+  b, break <addr>      set a breakpoint
+  d, delete <addr>     remove a breakpoint
+  w, watch <addr>      stop when the memory cell changes
+  u, unwatch <addr>    remove a watchpoint
+  x, mem <addr> [n]    dump n memory cells, starting at addr
+  r, regs              show instruction pointer and relative base
+  l, list [addr] [n]   disassemble n instructions, starting at addr
+  i, input <values>    queue input values
+  h, help              show this help
+  q, quit              exit";
+
+/// Why execution stopped.
+#[derive(Debug)]
+pub enum Stop<W = i64> {
+    /// The requested number of instructions was executed.
+    Stepped,
+    Breakpoint(usize),
+    Watchpoint {
+        address: usize,
+        old: W,
+        new: W,
+    },
+    NeedsInput,
+    Halted,
+    /// `continue` ran into its instruction limit.
+    Limit,
+    Error(IntcodeError<W>),
+}
+
+/// An interactive debugger around an Intcode machine.
+///
+/// The debugger is driven by text commands, see `HELP`, and answers with text,
+/// so it can be used from a terminal as well as from tests.
+pub struct Debugger<W = i64> {
+    machine: Intcode<W>,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeMap<usize, W>,
+    outputs: Vec<W>,
+}
+
+impl<W: Word> Debugger<W> {
+    pub fn new(machine: Intcode<W>) -> Debugger<W> {
+        Debugger {
+            machine,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+            outputs: vec![],
+        }
+    }
+
+    pub fn machine(&self) -> &Intcode<W> {
+        &self.machine
+    }
+
+    /// All values the program has written so far.
+    pub fn outputs(&self) -> &[W] {
+        &self.outputs
+    }
+
+    pub fn set_breakpoint(&mut self, address: usize) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: usize) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn set_watchpoint(&mut self, address: usize) {
+        let value = self.machine.memory().get(address);
+        self.watchpoints.insert(address, value);
+    }
+
+    pub fn remove_watchpoint(&mut self, address: usize) -> bool {
+        self.watchpoints.remove(&address).is_some()
+    }
+
+    /// Executes a single instruction, recording outputs and checking
+    /// watchpoints.
+    fn step_once(&mut self) -> Option<Stop<W>> {
+        match self.machine.step() {
+            Ok(Status::Running) => {}
+            Ok(Status::Output(value)) => self.outputs.push(value),
+            Ok(Status::NeedsInput) => return Some(Stop::NeedsInput),
+            Ok(Status::Halted) => return Some(Stop::Halted),
+            Err(error) => return Some(Stop::Error(error)),
+        }
+
+        for (address, old) in self.watchpoints.iter_mut() {
+            let new = self.machine.memory().get(*address);
+            if new != *old {
+                let old = std::mem::replace(old, new.clone());
+                return Some(Stop::Watchpoint {
+                    address: *address,
+                    old,
+                    new,
+                });
+            }
+        }
+
+        None
+    }
+
+    /// Executes `count` instructions, stopping early on anything noteworthy.
+    pub fn step(&mut self, count: usize) -> Stop<W> {
+        for _ in 0..count {
+            if let Some(stop) = self.step_once() {
+                return stop;
+            }
+        }
+
+        Stop::Stepped
+    }
+
+    /// Runs until a breakpoint or watchpoint is hit, the program waits for
+    /// input, halts or fails.
+    pub fn resume(&mut self) -> Stop<W> {
+        for _ in 0..CONTINUE_LIMIT {
+            if let Some(stop) = self.step_once() {
+                return stop;
+            }
+
+            let position = self.machine.position();
+            if self.breakpoints.contains(&position) {
+                return Stop::Breakpoint(position);
+            }
+        }
+
+        Stop::Limit
+    }
+
+    /// Executes a debugger command and returns the text to show.
+    pub fn execute(&mut self, command: &str) -> String {
+        let mut words = command.split_whitespace();
+        let name = match words.next() {
+            Some(name) => name,
+            None => return String::new(),
+        };
+        let arguments: Vec<&str> = words.collect();
+
+        match self.dispatch(name, &arguments) {
+            Ok(text) => text,
+            Err(message) => format!("error: {}\n", message),
+        }
+    }
+
+    fn dispatch(&mut self, name: &str, arguments: &[&str]) -> Result<String, String> {
+        let number = |index: usize, default: Option<usize>| -> Result<usize, String> {
+            match arguments.get(index) {
+                Some(argument) => argument
+                    .parse()
+                    .map_err(|_| format!("`{}` is not a valid number", argument)),
+                None => default.ok_or_else(|| format!("`{}` needs an address", name)),
+            }
+        };
+
+        match name {
+            "s" | "step" => {
+                let outputs = self.outputs.len();
+                let stop = self.step(number(0, Some(1))?);
+                Ok(self.report(outputs, &stop))
+            }
+            "c" | "continue" => {
+                let outputs = self.outputs.len();
+                let stop = self.resume();
+                Ok(self.report(outputs, &stop))
+            }
+            "b" | "break" => {
+                let address = number(0, None)?;
+                self.set_breakpoint(address);
+                Ok(format!("breakpoint at {}\n", address))
+            }
+            "d" | "delete" => {
+                let address = number(0, None)?;
+                if self.remove_breakpoint(address) {
+                    Ok(format!("removed breakpoint at {}\n", address))
+                } else {
+                    Err(format!("no breakpoint at {}", address))
+                }
+            }
+            "w" | "watch" => {
+                let address = number(0, None)?;
+                self.set_watchpoint(address);
+                Ok(format!("watching [{}]\n", address))
+            }
+            "u" | "unwatch" => {
+                let address = number(0, None)?;
+                if self.remove_watchpoint(address) {
+                    Ok(format!("removed watchpoint at {}\n", address))
+                } else {
+                    Err(format!("no watchpoint at {}", address))
+                }
+            }
+            "x" | "mem" => Ok(self.dump(number(0, None)?, number(1, Some(8))?)),
+            "r" | "regs" => Ok(self.registers()),
+            "l" | "list" => {
+                let position = self.machine.position();
+                Ok(self.list(number(0, Some(position))?, number(1, Some(10))?))
+            }
+            "i" | "input" => {
+                if arguments.is_empty() {
+                    return Err("`input` needs at least one value".to_string());
+                }
+                let values = arguments
+                    .iter()
+                    .map(|argument| {
+                        argument
+                            .parse()
+                            .map_err(|_| format!("`{}` is not a valid word", argument))
+                    })
+                    .collect::<Result<Vec<W>, String>>()?;
+                for value in values {
+                    self.machine.provide_input(value);
+                }
+                Ok(format!("queued {} input(s)\n", arguments.len()))
+            }
+            "h" | "help" => Ok(format!("{}\n", HELP)),
+            _ => Err(format!("unknown command `{}`, try `help`", name)),
+        }
+    }
+
+    fn report(&self, outputs: usize, stop: &Stop<W>) -> String {
+        let mut text = String::new();
+
+        for value in &self.outputs[outputs..] {
+            writeln!(text, "output: {}", value).unwrap();
+        }
+
+        match stop {
+            Stop::Stepped => {}
+            Stop::Breakpoint(address) => writeln!(text, "breakpoint at {}", address).unwrap(),
+            Stop::Watchpoint { address, old, new } => {
+                writeln!(text, "watchpoint [{}]: {} -> {}", address, old, new).unwrap()
+            }
+            Stop::NeedsInput => writeln!(text, "waiting for input").unwrap(),
+            Stop::Halted => writeln!(text, "halted").unwrap(),
+            Stop::Limit => writeln!(text, "stopped after {} instructions", CONTINUE_LIMIT).unwrap(),
+            Stop::Error(error) => writeln!(text, "error: {}", error).unwrap(),
+        }
+
+        text + &self.list(self.machine.position(), 1)
+    }
+
+    fn registers(&self) -> String {
+        format!(
+            "ip = {}\nrb = {}\n",
+            self.machine.position(),
+            self.machine.relative_base()
+        )
+    }
+
+    fn dump(&self, start: usize, count: usize) -> String {
+        let mut text = String::new();
+        // Stops at the end of the address space instead of wrapping around.
+        let end = start.saturating_add(count);
+
+        for row in (start..end).step_by(8) {
+            let end = row.saturating_add(8).min(end);
+            let words: Vec<String> = (row..end)
+                .map(|address| self.machine.memory().get(address).to_string())
+                .collect();
+            writeln!(text, "{:>5}: {}", row, words.join(" ")).unwrap();
+        }
+
+        text
+    }
+
+    fn list(&self, start: usize, count: usize) -> String {
+        let memory = self.machine.memory();
+        let mut text = String::new();
+        let mut address = start;
+
+        for _ in 0..count {
+            let marker = if address == self.machine.position() {
+                '>'
+            } else if self.breakpoints.contains(&address) {
+                '*'
+            } else {
+                ' '
+            };
+
+            match Instruction::decode(memory, address) {
+                Some(instruction) => {
+                    writeln!(text, "{}{:>5}: {}", marker, address, instruction).unwrap();
+                    match address.checked_add(instruction.size()) {
+                        Some(next) => address = next,
+                        None => break,
+                    }
+                }
+                None => {
+                    writeln!(
+                        text,
+                        "{}{:>5}: data {}",
+                        marker,
+                        address,
+                        memory.get(address)
+                    )
+                    .unwrap();
+                    match address.checked_add(1) {
+                        Some(next) => address = next,
+                        None => break,
+                    }
+                }
+            }
+        }
+
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::assemble::assemble;
+    use super::*;
+
+    fn debugger() -> Debugger {
+        let program = assemble(
+            "
+            loop:   in [value]
+                    jf [value], #end
+                    mul [value], #2, [value]
+                    out [value]
+                    jt #1, #loop
+            end:    hlt
+            value:  data 0
+            ",
+        )
+        .unwrap();
+
+        Debugger::new(Intcode::new(program))
+    }
+
+    #[test]
+    fn step_and_input() {
+        let mut debugger = debugger();
+
+        assert_eq!(
+            debugger.execute("step"),
+            "waiting for input\n>    0: in [15]\n"
+        );
+        assert_eq!(debugger.execute("i 21 0"), "queued 2 input(s)\n");
+        assert_eq!(debugger.execute("s 2"), ">    5: mul [15], #2, [15]\n");
+        assert_eq!(debugger.execute("s 2"), "output: 42\n>   11: jt #1, #0\n");
+        assert_eq!(debugger.execute("c"), "halted\n>   14: hlt\n");
+        assert_eq!(debugger.outputs(), &[42]);
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut debugger = debugger();
+        debugger.execute("input 1 2 0");
+        debugger.execute("break 9");
+
+        assert_eq!(debugger.execute("c"), "breakpoint at 9\n>    9: out [15]\n");
+        assert_eq!(
+            debugger.execute("continue"),
+            "output: 2\nbreakpoint at 9\n>    9: out [15]\n"
+        );
+        assert_eq!(debugger.execute("d 9"), "removed breakpoint at 9\n");
+        assert_eq!(debugger.execute("c"), "output: 4\nhalted\n>   14: hlt\n");
+    }
+
+    #[test]
+    fn watchpoints() {
+        let mut debugger = debugger();
+        debugger.execute("i 3");
+        debugger.execute("w 15");
+
+        assert_eq!(
+            debugger.execute("c"),
+            "watchpoint [15]: 0 -> 3\n>    2: jf [15], #14\n"
+        );
+        assert_eq!(
+            debugger.execute("c"),
+            "watchpoint [15]: 3 -> 6\n>    9: out [15]\n"
+        );
+        assert_eq!(
+            debugger.execute("c"),
+            "output: 6\nwaiting for input\n>    0: in [15]\n"
+        );
+    }
+
+    #[test]
+    fn inspect() {
+        let mut debugger = debugger();
+        debugger.execute("b 5");
+
+        assert_eq!(debugger.execute("regs"), "ip = 0\nrb = 0\n");
+        assert_eq!(
+            debugger.execute("x 8 10"),
+            "    8: 15 4 15 1105 1 0 99 0\n   16: 0 0\n"
+        );
+        assert_eq!(
+            debugger.execute("l 0 3"),
+            ">    0: in [15]\n     2: jf [15], #14\n*    5: mul [15], #2, [15]\n"
+        );
+    }
+
+    #[test]
+    fn command_errors() {
+        let mut debugger = debugger();
+
+        assert_eq!(debugger.execute(""), "");
+        assert_eq!(
+            debugger.execute("frobnicate"),
+            "error: unknown command `frobnicate`, try `help`\n"
+        );
+        assert_eq!(debugger.execute("b"), "error: `b` needs an address\n");
+        assert_eq!(
+            debugger.execute("x ten"),
+            "error: `ten` is not a valid number\n"
+        );
+        assert_eq!(debugger.execute("d 3"), "error: no breakpoint at 3\n");
+        assert_eq!(
+            debugger.execute("i"),
+            "error: `input` needs at least one value\n"
+        );
+
+        // Nothing is queued unless every value parses.
+        assert_eq!(
+            debugger.execute("i 1 x 2"),
+            "error: `x` is not a valid word\n"
+        );
+        assert_eq!(
+            debugger.execute("s"),
+            "waiting for input\n>    0: in [15]\n"
+        );
+    }
+
+    #[test]
+    fn end_of_address_space() {
+        let debugger = debugger();
+        let last = usize::MAX - 1;
+
+        assert_eq!(debugger.dump(last, 8), format!("{:>5}: 0\n", last));
+        assert_eq!(
+            debugger.list(last, 3),
+            format!(" {}: data 0\n {}: data 0\n", last, usize::MAX)
+        );
+    }
+}
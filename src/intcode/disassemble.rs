@@ -0,0 +1,163 @@
+use super::{Instruction, Memory, Parameter, Word};
+use std::fmt;
+
+/// The maximum number of data words shown on a single line.
+const DATA_PER_LINE: usize = 8;
+
+/// A line of a disassembled program.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Line<W = i64> {
+    Code {
+        address: usize,
+        instruction: Instruction<W>,
+    },
+    Data {
+        address: usize,
+        words: Vec<W>,
+    },
+}
+
+/// Disassembles a program image with a linear sweep.
+///
+/// Starting at address 0, every word is decoded as an instruction. Words that
+/// do not form a valid instruction, write to an immediate mode parameter,
+/// have mode digits the operation does not use or whose parameters would
+/// reach past the end of the image, are shown as data. Listings can be
+/// assembled again, see `assemble`.
+pub fn disassemble<W: Word>(memory: &Memory<W>) -> Vec<Line<W>> {
+    let mut lines: Vec<Line<W>> = vec![];
+    let mut address = 0;
+
+    while address < memory.len() {
+        match Instruction::decode(memory, address) {
+            Some(instruction)
+                if address + instruction.size() <= memory.len()
+                    && !has_immediate_target(&instruction)
+                    && memory.get(address).to_i64() == encode(&instruction) =>
+            {
+                let size = instruction.size();
+                lines.push(Line::Code {
+                    address,
+                    instruction,
+                });
+                address += size;
+            }
+            _ => {
+                let word = memory.get(address);
+                match lines.last_mut() {
+                    Some(Line::Data { words, .. }) if words.len() < DATA_PER_LINE => {
+                        words.push(word)
+                    }
+                    _ => lines.push(Line::Data {
+                        address,
+                        words: vec![word],
+                    }),
+                }
+                address += 1;
+            }
+        }
+    }
+
+    lines
+}
+
+fn has_immediate_target<W>(instruction: &Instruction<W>) -> bool {
+    match instruction.operation.target() {
+        Some(index) => matches!(instruction.parameters[index], Parameter::Immediate(_)),
+        None => false,
+    }
+}
+
+/// The opcode and parameter modes of an instruction, the way `assemble`
+/// writes them.
+fn encode<W>(instruction: &Instruction<W>) -> Option<i64> {
+    let (word, _) = instruction.parameters.iter().try_fold(
+        (instruction.operation.opcode(), 100i64),
+        |(word, factor), parameter| {
+            let mode = match parameter {
+                Parameter::Position(_) => 0,
+                Parameter::Immediate(_) => 1,
+                Parameter::Relative(_) => 2,
+            };
+            Some((word.checked_add(mode * factor)?, factor.checked_mul(10)?))
+        },
+    )?;
+
+    Some(word)
+}
+
+/// Disassembles a program image into a printable listing, one line per
+/// instruction.
+pub fn listing<W: Word>(memory: &Memory<W>) -> String {
+    disassemble(memory)
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+impl<W: Word> fmt::Display for Line<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Line::Code {
+                address,
+                instruction,
+            } => write!(f, "{:>5}: {}", address, instruction),
+            Line::Data { address, words } => {
+                let words: Vec<String> = words.iter().map(|word| word.to_string()).collect();
+                write!(f, "{:>5}: data {}", address, words.join(", "))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instructions() {
+        let memory = Memory::new(vec![1002, 4, 3, 4, 33, 21101, -7, 5, 3, 99]);
+        assert_eq!(
+            listing(&memory),
+            "    0: mul [4], #3, [4]\n    4: data 33\n    5: add #-7, #5, rb+3\n    9: hlt\n"
+        );
+    }
+
+    #[test]
+    fn data_regions() {
+        let memory = Memory::new(vec![4, 11, 1105, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 209, -1]);
+        let lines = disassemble(&memory);
+
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[1].to_string(), "    2: jt #1, #0");
+        assert_eq!(
+            lines[2],
+            Line::Data {
+                address: 5,
+                words: vec![0; 8]
+            }
+        );
+        assert_eq!(lines[3].to_string(), "   13: arb rb-1");
+    }
+
+    #[test]
+    fn immediate_target() {
+        let memory = Memory::new(vec![1101, 1, 2, 3, 11101, 1, 2, 3]);
+        assert_eq!(
+            listing(&memory),
+            "    0: add #1, #2, [3]\n    4: data 11101, 1, 2, 3\n"
+        );
+    }
+
+    #[test]
+    fn unused_modes() {
+        let memory = Memory::new(vec![1099, 10104, 5, 99]);
+        assert_eq!(listing(&memory), "    0: data 1099, 10104, 5\n    3: hlt\n");
+    }
+
+    #[test]
+    fn truncated_instruction() {
+        let memory = Memory::new(vec![104, 1, 1, 2]);
+        assert_eq!(listing(&memory), "    0: out #1\n    2: data 1, 2\n");
+    }
+}
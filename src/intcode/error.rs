@@ -0,0 +1,270 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+/// Everything that can go wrong while loading or running an Intcode program.
+///
+/// Failures during execution carry the position of the failing instruction and
+/// its raw opcode, including the parameter modes. The machine is left on that
+/// instruction, so its state can be inspected afterwards.
+#[derive(Debug)]
+pub enum IntcodeError<W = i64> {
+    /// The program file could not be read.
+    Io(io::Error),
+    /// A word of the program file is not a valid integer.
+    Parse { index: usize, token: String },
+    /// Execution ran past the end of the program without reaching a halt
+    /// instruction. The opcode is whatever was written there, usually zero.
+    EndOfProgram { position: usize, opcode: W },
+    /// The opcode does not name a known operation.
+    UnknownOperation { position: usize, opcode: W },
+    /// A parameter uses a mode other than position, immediate or relative.
+    UnknownMode {
+        position: usize,
+        opcode: W,
+        mode: i64,
+    },
+    /// An instruction tries to write to an immediate mode parameter.
+    ImmediateTarget { position: usize, opcode: W },
+    /// An input instruction was executed, but no input is available.
+    MissingInput { position: usize, opcode: W },
+    /// An output instruction was executed, but the output sink is closed.
+    OutputClosed { position: usize, opcode: W },
+    /// A parameter or jump refers to a negative or otherwise unusable address.
+    InvalidAddress {
+        position: usize,
+        opcode: W,
+        address: W,
+    },
+    /// The instruction does not have the parameters its operation takes.
+    MalformedInstruction { position: usize, opcode: W },
+    /// The result of an arithmetic instruction does not fit into a word.
+    Overflow { position: usize, opcode: W },
+    /// A custom operation returned `Action::Write`, but takes no parameter to
+    /// write to.
+    MissingTarget { position: usize, opcode: W },
+    /// The machine executed as many instructions as its limits allow.
+    InstructionLimit {
+        position: usize,
+        opcode: W,
+        executed: u64,
+    },
+    /// The machine ran for as long as its limits allow.
+    Timeout {
+        position: usize,
+        opcode: W,
+        elapsed: Duration,
+    },
+    /// An instruction tries to write beyond the memory limit.
+    MemoryLimit {
+        position: usize,
+        opcode: W,
+        address: usize,
+    },
+    /// The program emitted as many outputs as its limits allow, and tries to
+    /// emit another one.
+    OutputLimit {
+        position: usize,
+        opcode: W,
+        outputs: usize,
+    },
+    /// The machine came back to a state it was in before without consuming
+    /// an input, so it would repeat the same `period` instructions forever.
+    /// `entry` is the position of the first instruction of the loop.
+    InfiniteLoop {
+        position: usize,
+        opcode: W,
+        entry: usize,
+        period: u64,
+    },
+}
+
+impl<W> IntcodeError<W> {
+    /// The position of the failing instruction, if the error happened during
+    /// execution.
+    pub fn position(&self) -> Option<usize> {
+        match self {
+            IntcodeError::Io(_) | IntcodeError::Parse { .. } => None,
+            IntcodeError::EndOfProgram { position, .. }
+            | IntcodeError::UnknownOperation { position, .. }
+            | IntcodeError::UnknownMode { position, .. }
+            | IntcodeError::ImmediateTarget { position, .. }
+            | IntcodeError::MissingInput { position, .. }
+            | IntcodeError::OutputClosed { position, .. }
+            | IntcodeError::InvalidAddress { position, .. }
+            | IntcodeError::MalformedInstruction { position, .. }
+            | IntcodeError::Overflow { position, .. }
+            | IntcodeError::MissingTarget { position, .. }
+            | IntcodeError::InstructionLimit { position, .. }
+            | IntcodeError::Timeout { position, .. }
+            | IntcodeError::MemoryLimit { position, .. }
+            | IntcodeError::OutputLimit { position, .. }
+            | IntcodeError::InfiniteLoop { position, .. } => Some(*position),
+        }
+    }
+
+    /// The raw opcode of the failing instruction, if the error happened
+    /// during execution.
+    pub fn opcode(&self) -> Option<&W> {
+        match self {
+            IntcodeError::Io(_) | IntcodeError::Parse { .. } => None,
+            IntcodeError::EndOfProgram { opcode, .. }
+            | IntcodeError::UnknownOperation { opcode, .. }
+            | IntcodeError::UnknownMode { opcode, .. }
+            | IntcodeError::ImmediateTarget { opcode, .. }
+            | IntcodeError::MissingInput { opcode, .. }
+            | IntcodeError::OutputClosed { opcode, .. }
+            | IntcodeError::InvalidAddress { opcode, .. }
+            | IntcodeError::MalformedInstruction { opcode, .. }
+            | IntcodeError::Overflow { opcode, .. }
+            | IntcodeError::MissingTarget { opcode, .. }
+            | IntcodeError::InstructionLimit { opcode, .. }
+            | IntcodeError::Timeout { opcode, .. }
+            | IntcodeError::MemoryLimit { opcode, .. }
+            | IntcodeError::OutputLimit { opcode, .. }
+            | IntcodeError::InfiniteLoop { opcode, .. } => Some(opcode),
+        }
+    }
+
+    /// Whether the machine stopped at one of its limits, see `Limits`. It is
+    /// left on the instruction it stopped at, so it can be resumed after the
+    /// limits were raised with `Intcode::set_limits`.
+    pub fn is_limit(&self) -> bool {
+        matches!(
+            self,
+            IntcodeError::InstructionLimit { .. }
+                | IntcodeError::Timeout { .. }
+                | IntcodeError::MemoryLimit { .. }
+                | IntcodeError::OutputLimit { .. }
+        )
+    }
+}
+
+impl<W: fmt::Display> fmt::Display for IntcodeError<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntcodeError::Io(error) => write!(f, "Failed to read program: {}", error),
+            IntcodeError::Parse { index, token } => {
+                write!(f, "Invalid word {:?} at index {}", token, index)
+            }
+            IntcodeError::EndOfProgram { position, .. } => write!(
+                f,
+                "Reached end of program at position {} without a halt",
+                position
+            ),
+            IntcodeError::UnknownOperation { position, opcode } => {
+                write!(f, "Unknown operation {} at position {}", opcode, position)
+            }
+            IntcodeError::UnknownMode {
+                position,
+                opcode,
+                mode,
+            } => write!(
+                f,
+                "Unknown mode {} in instruction {} at position {}",
+                mode, opcode, position
+            ),
+            IntcodeError::ImmediateTarget { position, opcode } => write!(
+                f,
+                "Target parameter of instruction {} at position {} should not be in immediate mode",
+                opcode, position
+            ),
+            IntcodeError::MissingInput { position, opcode } => write!(
+                f,
+                "Too few inputs provided for instruction {} at position {}",
+                opcode, position
+            ),
+            IntcodeError::OutputClosed { position, opcode } => write!(
+                f,
+                "Output of instruction {} at position {} was not accepted",
+                opcode, position
+            ),
+            IntcodeError::InvalidAddress {
+                position,
+                opcode,
+                address,
+            } => write!(
+                f,
+                "Invalid address {} in instruction {} at position {}",
+                address, opcode, position
+            ),
+            IntcodeError::MalformedInstruction { position, opcode } => write!(
+                f,
+                "Instruction {} at position {} has the wrong number of parameters",
+                opcode, position
+            ),
+            IntcodeError::Overflow { position, opcode } => write!(
+                f,
+                "Arithmetic overflow in instruction {} at position {}",
+                opcode, position
+            ),
+            IntcodeError::MissingTarget { position, opcode } => write!(
+                f,
+                "Instruction {} at position {} wrote a value, but has no target parameter",
+                opcode, position
+            ),
+            IntcodeError::InstructionLimit {
+                position,
+                opcode,
+                executed,
+            } => write!(
+                f,
+                "Stopped before instruction {} at position {} after {} instructions",
+                opcode, position, executed
+            ),
+            IntcodeError::Timeout {
+                position,
+                opcode,
+                elapsed,
+            } => write!(
+                f,
+                "Stopped before instruction {} at position {} after {:?}",
+                opcode, position, elapsed
+            ),
+            IntcodeError::MemoryLimit {
+                position,
+                opcode,
+                address,
+            } => write!(
+                f,
+                "Address {} in instruction {} at position {} is beyond the memory limit",
+                address, opcode, position
+            ),
+            IntcodeError::OutputLimit {
+                position,
+                opcode,
+                outputs,
+            } => write!(
+                f,
+                "Output of instruction {} at position {} exceeds the limit of {} outputs",
+                opcode, position, outputs
+            ),
+            IntcodeError::InfiniteLoop {
+                position,
+                opcode,
+                entry,
+                period,
+            } => write!(
+                f,
+                "Stopped before instruction {} at position {} in an infinite loop of {} instructions entered at position {}",
+                opcode, position, period, entry
+            ),
+        }
+    }
+}
+
+impl<W: fmt::Debug + fmt::Display> Error for IntcodeError<W> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            IntcodeError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl<W> From<io::Error> for IntcodeError<W> {
+    fn from(error: io::Error) -> Self {
+        IntcodeError::Io(error)
+    }
+}
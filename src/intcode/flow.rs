@@ -0,0 +1,460 @@
+//! Control-flow graphs of Intcode programs.
+//!
+//! The graph is recovered statically, without running the program: starting
+//! at address 0, instructions are decoded along every path control can take.
+//! Only jumps with an immediate target can be followed. Jumps to a computed
+//! target are flagged as indirect, and so are writes to addresses the graph
+//! holds code at, since the program may rewrite its own instructions before
+//! they run.
+
+use super::{Instruction, Memory, Operation, Parameter, Word};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+/// How control leaves a basic block.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Exit {
+    /// The block ends with a halt.
+    Halt,
+    /// The block runs into the next block, which is the target of a jump.
+    Fallthrough,
+    /// The block ends with a jump to an immediate target.
+    Jump,
+    /// The block ends with a jump to a target computed at run time.
+    Indirect,
+    /// The block runs into a word that is not a valid instruction, past the
+    /// end of the program, or jumps to an invalid address.
+    Invalid,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EdgeKind {
+    /// Control continues with the next instruction.
+    Next,
+    /// Control follows a jump.
+    Taken,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Edge {
+    pub target: usize,
+    pub kind: EdgeKind,
+}
+
+/// A run of instructions that is only entered at its first instruction and
+/// only left after its last one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block<W = i64> {
+    pub start: usize,
+    /// The instructions by their address. Empty if the block starts at an
+    /// invalid instruction.
+    pub instructions: Vec<(usize, Instruction<W>)>,
+    pub exit: Exit,
+    pub successors: Vec<Edge>,
+}
+
+/// An instruction that writes to an address the graph holds code at.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CodeWrite {
+    /// The address of the writing instruction.
+    pub position: usize,
+    /// The address written to.
+    pub address: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct FlowGraph<W = i64> {
+    /// The basic blocks by their first address.
+    pub blocks: BTreeMap<usize, Block<W>>,
+    /// The addresses of jumps to a target computed at run time.
+    pub indirect_jumps: Vec<usize>,
+    pub code_writes: Vec<CodeWrite>,
+}
+
+impl<W: Word> FlowGraph<W> {
+    /// Recovers the control-flow graph of a program image that starts at
+    /// address 0.
+    pub fn build(memory: &Memory<W>) -> FlowGraph<W> {
+        let mut instructions: BTreeMap<usize, Instruction<W>> = BTreeMap::new();
+        let mut invalid: BTreeSet<usize> = BTreeSet::new();
+        let mut leaders: BTreeSet<usize> = BTreeSet::new();
+        let mut indirect_jumps = vec![];
+
+        leaders.insert(0);
+        let mut pending = vec![0];
+        while let Some(address) = pending.pop() {
+            if instructions.contains_key(&address) || invalid.contains(&address) {
+                continue;
+            }
+
+            let instruction = match decode(memory, address) {
+                Some(instruction) => instruction,
+                None => {
+                    invalid.insert(address);
+                    continue;
+                }
+            };
+            let next = address + instruction.size();
+
+            let (falls_through, jumps) = branches(&instruction);
+            if is_jump(&instruction) {
+                if falls_through {
+                    leaders.insert(next);
+                }
+                if jumps {
+                    match jump_target(&instruction) {
+                        Some(Some(target)) => {
+                            leaders.insert(target);
+                            pending.push(target);
+                        }
+                        Some(None) => {}
+                        None => indirect_jumps.push(address),
+                    }
+                }
+            }
+            if falls_through {
+                pending.push(next);
+            }
+
+            instructions.insert(address, instruction);
+        }
+
+        let mut blocks = BTreeMap::new();
+        for &start in &leaders {
+            let block = block_at(start, &instructions, &leaders);
+            blocks.insert(start, block);
+        }
+
+        let mut code: BTreeSet<usize> = invalid;
+        for (address, instruction) in &instructions {
+            code.extend(*address..address + instruction.size());
+        }
+
+        let code_writes = instructions
+            .iter()
+            .filter_map(|(position, instruction)| {
+                let index = instruction.operation.target()?;
+                match &instruction.parameters[index] {
+                    Parameter::Position(address) => {
+                        let address = address.to_address()?;
+                        if code.contains(&address) {
+                            Some(CodeWrite {
+                                position: *position,
+                                address,
+                            })
+                        } else {
+                            None
+                        }
+                    }
+                    _ => None,
+                }
+            })
+            .collect();
+
+        indirect_jumps.sort_unstable();
+        FlowGraph {
+            blocks,
+            indirect_jumps,
+            code_writes,
+        }
+    }
+
+    /// Renders the graph in the Graphviz DOT language, e.g. for
+    /// `dot -Tsvg`.
+    ///
+    /// Blocks that end with an indirect jump or contain a write into code are
+    /// drawn in red, jumps that are taken as solid edges and fallthroughs as
+    /// dashed ones.
+    pub fn dot(&self) -> String {
+        let writers: BTreeSet<usize> = self.code_writes.iter().map(|w| w.position).collect();
+        let mut out = String::new();
+
+        out.push_str("digraph intcode {\n");
+        out.push_str("    node [shape=box, fontname=monospace];\n");
+
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            for (address, instruction) in &block.instructions {
+                write!(label, "{}: {}\\l", address, instruction).unwrap();
+            }
+            if block.exit == Exit::Invalid {
+                match block.instructions.last() {
+                    Some((_, instruction)) if is_jump(instruction) => {
+                        label.push_str("jump to an invalid address\\l")
+                    }
+                    Some((address, instruction)) => {
+                        write!(label, "{}: invalid\\l", address + instruction.size()).unwrap()
+                    }
+                    None => write!(label, "{}: invalid\\l", block.start).unwrap(),
+                }
+            }
+
+            let flagged = block.exit == Exit::Indirect
+                || block
+                    .instructions
+                    .iter()
+                    .any(|(address, _)| writers.contains(address));
+            let color = if flagged { ", color=red" } else { "" };
+            writeln!(out, "    b{} [label=\"{}\"{}];", block.start, label, color).unwrap();
+        }
+
+        for block in self.blocks.values() {
+            for edge in &block.successors {
+                let style = match edge.kind {
+                    EdgeKind::Next => " [style=dashed]",
+                    EdgeKind::Taken => "",
+                };
+                writeln!(out, "    b{} -> b{}{};", block.start, edge.target, style).unwrap();
+            }
+        }
+
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// Decodes the instruction at `address`, unless it is past the end of the
+/// program.
+fn decode<W: Word>(memory: &Memory<W>, address: usize) -> Option<Instruction<W>> {
+    if address >= memory.len() {
+        return None;
+    }
+    Instruction::decode(memory, address)
+}
+
+fn is_jump<W>(instruction: &Instruction<W>) -> bool {
+    matches!(
+        instruction.operation,
+        Operation::JumpIfTrue | Operation::JumpIfFalse
+    )
+}
+
+/// Whether control may continue with the next instruction, and whether it
+/// may jump. Jumps on an immediate condition are either always or never
+/// taken.
+fn branches<W: Word>(instruction: &Instruction<W>) -> (bool, bool) {
+    let jump_if = match instruction.operation {
+        Operation::Halt => return (false, false),
+        Operation::JumpIfTrue => true,
+        Operation::JumpIfFalse => false,
+        _ => return (true, false),
+    };
+
+    match &instruction.parameters[0] {
+        Parameter::Immediate(condition) => {
+            let taken = condition.is_zero() != jump_if;
+            (!taken, taken)
+        }
+        _ => (true, true),
+    }
+}
+
+/// The target of a jump: `None` if it is computed at run time, and
+/// `Some(None)` if it is immediate but not a valid address, which fails when
+/// the jump is taken.
+fn jump_target<W: Word>(instruction: &Instruction<W>) -> Option<Option<usize>> {
+    match &instruction.parameters[1] {
+        Parameter::Immediate(target) => Some(target.to_address()),
+        _ => None,
+    }
+}
+
+/// Collects the basic block starting at `start`.
+fn block_at<W: Word>(
+    start: usize,
+    instructions: &BTreeMap<usize, Instruction<W>>,
+    leaders: &BTreeSet<usize>,
+) -> Block<W> {
+    let mut block = Block {
+        start,
+        instructions: vec![],
+        exit: Exit::Invalid,
+        successors: vec![],
+    };
+
+    let mut address = start;
+    while let Some(instruction) = instructions.get(&address) {
+        let next = address + instruction.size();
+        block.instructions.push((address, instruction.clone()));
+
+        let (falls_through, jumps) = branches(instruction);
+        if instruction.operation == Operation::Halt {
+            block.exit = Exit::Halt;
+            return block;
+        }
+        if is_jump(instruction) {
+            block.exit = Exit::Jump;
+            if jumps {
+                match jump_target(instruction) {
+                    Some(Some(target)) => block.successors.push(Edge {
+                        target,
+                        kind: EdgeKind::Taken,
+                    }),
+                    Some(None) => block.exit = Exit::Invalid,
+                    None => block.exit = Exit::Indirect,
+                }
+            }
+            if falls_through {
+                block.successors.push(Edge {
+                    target: next,
+                    kind: EdgeKind::Next,
+                });
+            }
+            return block;
+        }
+        if leaders.contains(&next) {
+            block.exit = Exit::Fallthrough;
+            block.successors.push(Edge {
+                target: next,
+                kind: EdgeKind::Next,
+            });
+            return block;
+        }
+
+        address = next;
+    }
+
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Intcode;
+    use super::*;
+
+    fn build(program: Vec<i64>) -> FlowGraph {
+        FlowGraph::build(&Memory::new(program))
+    }
+
+    fn starts(graph: &FlowGraph) -> Vec<usize> {
+        graph.blocks.keys().cloned().collect()
+    }
+
+    #[test]
+    fn loop_blocks() {
+        // Counts down from 3, outputting every value, then halts.
+        let graph = build(vec![4, 11, 1001, 11, -1, 11, 1005, 11, 0, 99, 0, 3]);
+
+        assert_eq!(starts(&graph), vec![0, 9]);
+        let body = &graph.blocks[&0];
+        assert_eq!(body.instructions.len(), 3);
+        assert_eq!(body.exit, Exit::Jump);
+        assert_eq!(
+            body.successors,
+            vec![
+                Edge {
+                    target: 0,
+                    kind: EdgeKind::Taken
+                },
+                Edge {
+                    target: 9,
+                    kind: EdgeKind::Next
+                }
+            ]
+        );
+        assert_eq!(graph.blocks[&9].exit, Exit::Halt);
+        assert!(graph.indirect_jumps.is_empty());
+        assert!(graph.code_writes.is_empty());
+    }
+
+    #[test]
+    fn jumps_into_a_block() {
+        // Jumps over the first output into the middle of the straight code.
+        let graph = build(vec![1105, 1, 5, 104, 1, 104, 2, 99]);
+
+        assert_eq!(starts(&graph), vec![0, 5]);
+        assert_eq!(graph.blocks[&0].successors.len(), 1);
+        assert_eq!(graph.blocks[&5].instructions.len(), 2);
+
+        // Unreachable code is not part of the graph.
+        assert!(graph
+            .blocks
+            .values()
+            .all(|block| block.instructions.iter().all(|(address, _)| *address != 3)));
+    }
+
+    #[test]
+    fn code_writes() {
+        // Reads the value the output instruction prints into its parameter.
+        let graph = build(vec![3, 3, 104, 0, 99]);
+
+        assert_eq!(starts(&graph), vec![0]);
+        assert_eq!(
+            graph.code_writes,
+            vec![CodeWrite {
+                position: 0,
+                address: 3
+            }]
+        );
+    }
+
+    #[test]
+    fn indirect_jumps() {
+        // Jumps to its input, unless it is zero.
+        let graph = build(vec![3, 9, 5, 9, 9, 104, 0, 99, 0, 0]);
+
+        assert_eq!(graph.indirect_jumps, vec![2]);
+        assert_eq!(starts(&graph), vec![0, 5]);
+        assert_eq!(graph.blocks[&0].exit, Exit::Indirect);
+        assert_eq!(
+            graph.blocks[&0].successors,
+            vec![Edge {
+                target: 5,
+                kind: EdgeKind::Next
+            }]
+        );
+        assert_eq!(graph.blocks[&5].exit, Exit::Halt);
+    }
+
+    #[test]
+    fn dot_output() {
+        let graph = build(vec![3, 7, 1006, 7, 7, 104, 1, 99]);
+
+        assert_eq!(
+            graph.dot(),
+            "digraph intcode {\n    \
+             node [shape=box, fontname=monospace];\n    \
+             b0 [label=\"0: in [7]\\l2: jf [7], #7\\l\", color=red];\n    \
+             b5 [label=\"5: out #1\\l\"];\n    \
+             b7 [label=\"7: hlt\\l\"];\n    \
+             b0 -> b7;\n    \
+             b0 -> b5 [style=dashed];\n    \
+             b5 -> b7 [style=dashed];\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn diagnostic_program() {
+        let mut memory = Intcode::from_file("inputs/day05.txt")
+            .unwrap()
+            .memory()
+            .clone();
+
+        // The system id is added to the word at 6 before it runs, so the
+        // image alone runs into invalid code.
+        let graph = FlowGraph::build(&memory);
+        assert_eq!(starts(&graph), vec![0]);
+        assert_eq!(graph.blocks[&0].exit, Exit::Invalid);
+        assert_eq!(
+            graph.code_writes,
+            vec![CodeWrite {
+                position: 2,
+                address: 6
+            }]
+        );
+
+        // With system id 5, it jumps to the tests of part two. Every failing
+        // test jumps to 99999, past the end of the program.
+        memory.set(6, 1105);
+        let graph = FlowGraph::build(&memory);
+        assert_eq!(graph.blocks[&0].successors[0].target, 238);
+        assert!(graph.blocks.contains_key(&99999));
+        assert_eq!(graph.blocks[&99999].exit, Exit::Invalid);
+
+        // One of the tests jumps to an address it reads from memory, which
+        // the graph cannot follow.
+        assert_eq!(graph.indirect_jumps, vec![288]);
+        assert_eq!(graph.blocks.len(), 14);
+    }
+}
@@ -0,0 +1,276 @@
+use super::isa::InstructionSet;
+use super::{Fault, Memory, Word};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Parameter<W = i64> {
+    Position(W),
+    Immediate(W),
+    Relative(W),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Add,
+    Multiply,
+    Input,
+    Output,
+    JumpIfTrue,
+    JumpIfFalse,
+    LessThan,
+    Equals,
+    AdjustRelativeBase,
+    Halt,
+    /// An operation registered in an `InstructionSet`. Its parameters come
+    /// from the registration.
+    Custom {
+        opcode: i64,
+        arity: usize,
+        /// The index of the parameter the operation writes to, if any.
+        target: Option<usize>,
+    },
+}
+
+/// A decoded instruction: an operation and its parameters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instruction<W = i64> {
+    pub operation: Operation,
+    pub parameters: Vec<Parameter<W>>,
+}
+
+/// The parameters of built-in operations, which fit inline.
+const INLINE_PARAMETERS: usize = 3;
+
+/// An instruction the way the machine executes it. Up to three parameters are
+/// kept inline, so decoding one does not allocate.
+#[derive(Clone)]
+pub(super) struct Decoded<W> {
+    pub(super) operation: Operation,
+    /// The arity of `operation`, which is looked up on every execution.
+    arity: usize,
+    inline: [Parameter<W>; INLINE_PARAMETERS],
+    /// The parameters of custom operations that take more than three.
+    spilled: Vec<Parameter<W>>,
+}
+
+impl Operation {
+    pub fn from_opcode(opcode: i64) -> Option<Operation> {
+        let operation = match opcode {
+            1 => Operation::Add,
+            2 => Operation::Multiply,
+            3 => Operation::Input,
+            4 => Operation::Output,
+            5 => Operation::JumpIfTrue,
+            6 => Operation::JumpIfFalse,
+            7 => Operation::LessThan,
+            8 => Operation::Equals,
+            9 => Operation::AdjustRelativeBase,
+            99 => Operation::Halt,
+            _ => return None,
+        };
+
+        Some(operation)
+    }
+
+    pub fn opcode(self) -> i64 {
+        match self {
+            Operation::Add => 1,
+            Operation::Multiply => 2,
+            Operation::Input => 3,
+            Operation::Output => 4,
+            Operation::JumpIfTrue => 5,
+            Operation::JumpIfFalse => 6,
+            Operation::LessThan => 7,
+            Operation::Equals => 8,
+            Operation::AdjustRelativeBase => 9,
+            Operation::Halt => 99,
+            Operation::Custom { opcode, .. } => opcode,
+        }
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Operation> {
+        let operation = match mnemonic {
+            "add" => Operation::Add,
+            "mul" => Operation::Multiply,
+            "in" => Operation::Input,
+            "out" => Operation::Output,
+            "jt" => Operation::JumpIfTrue,
+            "jf" => Operation::JumpIfFalse,
+            "lt" => Operation::LessThan,
+            "eq" => Operation::Equals,
+            "arb" => Operation::AdjustRelativeBase,
+            "hlt" => Operation::Halt,
+            _ => return None,
+        };
+
+        Some(operation)
+    }
+
+    /// The number of parameters the operation takes.
+    pub fn arity(self) -> usize {
+        match self {
+            Operation::Add | Operation::Multiply | Operation::LessThan | Operation::Equals => 3,
+            Operation::JumpIfTrue | Operation::JumpIfFalse => 2,
+            Operation::Input | Operation::Output | Operation::AdjustRelativeBase => 1,
+            Operation::Halt => 0,
+            Operation::Custom { arity, .. } => arity,
+        }
+    }
+
+    /// The index of the parameter the operation writes to, if any.
+    pub fn target(self) -> Option<usize> {
+        match self {
+            Operation::Add | Operation::Multiply | Operation::LessThan | Operation::Equals => {
+                Some(2)
+            }
+            Operation::Input => Some(0),
+            Operation::Custom { target, .. } => target,
+            _ => None,
+        }
+    }
+
+    /// The name of the operation in assembly. Custom operations are all
+    /// called `op`, and shown with their opcode, e.g. `op42`.
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Operation::Add => "add",
+            Operation::Multiply => "mul",
+            Operation::Input => "in",
+            Operation::Output => "out",
+            Operation::JumpIfTrue => "jt",
+            Operation::JumpIfFalse => "jf",
+            Operation::LessThan => "lt",
+            Operation::Equals => "eq",
+            Operation::AdjustRelativeBase => "arb",
+            Operation::Halt => "hlt",
+            Operation::Custom { .. } => "op",
+        }
+    }
+}
+
+impl<W: Word> Instruction<W> {
+    /// Decodes the instruction at `address`, or returns `None` if the memory at
+    /// this address does not hold a valid instruction.
+    pub fn decode(memory: &Memory<W>, address: usize) -> Option<Instruction<W>> {
+        decode(memory, address, None)
+            .ok()
+            .map(|decoded| decoded.to_instruction())
+    }
+
+    /// Decodes the instruction at `address` like `decode`, accepting only the
+    /// operations of an instruction set.
+    pub fn decode_with(
+        memory: &Memory<W>,
+        address: usize,
+        isa: &InstructionSet<W>,
+    ) -> Option<Instruction<W>> {
+        decode(memory, address, Some(isa))
+            .ok()
+            .map(|decoded| decoded.to_instruction())
+    }
+
+    /// The number of words the instruction occupies in memory.
+    pub fn size(&self) -> usize {
+        1 + self.parameters.len()
+    }
+}
+
+impl<W: Word> Decoded<W> {
+    pub(super) fn parameters(&self) -> &[Parameter<W>] {
+        match self.arity {
+            arity if arity <= INLINE_PARAMETERS => &self.inline[..arity],
+            _ => &self.spilled,
+        }
+    }
+
+    /// The number of words the instruction occupies in memory.
+    pub(super) fn size(&self) -> usize {
+        1 + self.arity
+    }
+
+    pub(super) fn to_instruction(&self) -> Instruction<W> {
+        Instruction {
+            operation: self.operation,
+            parameters: self.parameters().to_vec(),
+        }
+    }
+}
+
+/// Decodes an instruction of `isa`, or of the full instruction set if there
+/// is none.
+pub(super) fn decode<W: Word>(
+    memory: &Memory<W>,
+    address: usize,
+    isa: Option<&InstructionSet<W>>,
+) -> Result<Decoded<W>, Fault<W>> {
+    let parameter_code = memory
+        .get(address)
+        .to_i64()
+        .ok_or(Fault::UnknownOperation)?;
+    let mut remainder = parameter_code / 100;
+    let opcode = parameter_code % 100;
+
+    let operation = match isa {
+        Some(isa) => isa.operation(opcode),
+        None => Operation::from_opcode(opcode),
+    }
+    .ok_or(Fault::UnknownOperation)?;
+    let arity = operation.arity();
+    let mut inline = [
+        Parameter::Immediate(W::zero()),
+        Parameter::Immediate(W::zero()),
+        Parameter::Immediate(W::zero()),
+    ];
+    let mut spilled = vec![];
+
+    for offset in 1..=arity {
+        let mode = match remainder % 10 {
+            0 => Parameter::Position,
+            1 => Parameter::Immediate,
+            2 => Parameter::Relative,
+            mode => return Err(Fault::UnknownMode(mode)),
+        };
+        remainder /= 10;
+
+        let parameter = mode(memory.get(address + offset));
+        if arity <= INLINE_PARAMETERS {
+            inline[offset - 1] = parameter;
+        } else {
+            spilled.push(parameter);
+        }
+    }
+
+    Ok(Decoded {
+        operation,
+        arity,
+        inline,
+        spilled,
+    })
+}
+
+impl<W: Word> fmt::Display for Parameter<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Parameter::Position(address) => write!(f, "[{}]", address),
+            Parameter::Immediate(value) => write!(f, "#{}", value),
+            Parameter::Relative(offset) if *offset < W::zero() => write!(f, "rb{}", offset),
+            Parameter::Relative(offset) => write!(f, "rb+{}", offset),
+        }
+    }
+}
+
+impl<W: Word> fmt::Display for Instruction<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.operation {
+            Operation::Custom { opcode, .. } => write!(f, "op{}", opcode)?,
+            operation => write!(f, "{}", operation.mnemonic())?,
+        }
+
+        for (index, parameter) in self.parameters.iter().enumerate() {
+            let separator = if index == 0 { " " } else { ", " };
+            write!(f, "{}{}", separator, parameter)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{Receiver, Sender, SyncSender};
+
+/// Something an Intcode machine can read its inputs from.
+pub trait InputSource<W> {
+    /// Returns the next input, or `None` if the source is exhausted.
+    ///
+    /// Implementations may block until an input is available, e.g. when
+    /// reading from a channel that is fed by another machine.
+    fn next_input(&mut self) -> Option<W>;
+}
+
+/// Something an Intcode machine can write its outputs to.
+pub trait OutputSink<W> {
+    /// Accepts an output. If the sink can no longer take values, the value is
+    /// handed back.
+    fn write_output(&mut self, value: W) -> Result<(), W>;
+}
+
+/// Adapts any iterator into an input source.
+pub struct IterInput<I>(pub I);
+
+impl<W, I: Iterator<Item = W>> InputSource<W> for IterInput<I> {
+    fn next_input(&mut self) -> Option<W> {
+        self.0.next()
+    }
+}
+
+impl<W> InputSource<W> for VecDeque<W> {
+    fn next_input(&mut self) -> Option<W> {
+        self.pop_front()
+    }
+}
+
+impl<W> InputSource<W> for Receiver<W> {
+    fn next_input(&mut self) -> Option<W> {
+        self.recv().ok()
+    }
+}
+
+impl<W, F: FnMut() -> Option<W>> InputSource<W> for F {
+    fn next_input(&mut self) -> Option<W> {
+        self()
+    }
+}
+
+impl<W> OutputSink<W> for Vec<W> {
+    fn write_output(&mut self, value: W) -> Result<(), W> {
+        self.push(value);
+        Ok(())
+    }
+}
+
+impl<W> OutputSink<W> for VecDeque<W> {
+    fn write_output(&mut self, value: W) -> Result<(), W> {
+        self.push_back(value);
+        Ok(())
+    }
+}
+
+impl<W> OutputSink<W> for Sender<W> {
+    fn write_output(&mut self, value: W) -> Result<(), W> {
+        self.send(value).map_err(|error| error.0)
+    }
+}
+
+impl<W> OutputSink<W> for SyncSender<W> {
+    fn write_output(&mut self, value: W) -> Result<(), W> {
+        self.send(value).map_err(|error| error.0)
+    }
+}
+
+impl<W, F: FnMut(W)> OutputSink<W> for F {
+    fn write_output(&mut self, value: W) -> Result<(), W> {
+        self(value);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Intcode, IntcodeError, Status};
+    use super::*;
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    /// Doubles every input until it reads a zero.
+    fn doubler() -> Intcode {
+        Intcode::new(vec![
+            3, 15, 1006, 15, 14, 102, 2, 15, 15, 4, 15, 1105, 1, 0, 99, 0,
+        ])
+    }
+
+    #[test]
+    fn iterator_input() {
+        let mut outputs = vec![];
+        doubler()
+            .run_with(&mut IterInput(vec![1, 2, 3, 0].into_iter()), &mut outputs)
+            .unwrap();
+
+        assert_eq!(outputs, vec![2, 4, 6]);
+    }
+
+    #[test]
+    fn queue_input_and_output() {
+        let mut inputs: VecDeque<i64> = vec![5, 0].into_iter().collect();
+        let mut outputs = VecDeque::new();
+        doubler().run_with(&mut inputs, &mut outputs).unwrap();
+
+        assert!(inputs.is_empty());
+        assert_eq!(outputs, vec![10]);
+    }
+
+    #[test]
+    fn closures() {
+        let mut counter = 3;
+        let mut input = || {
+            counter -= 1;
+            Some(counter)
+        };
+        let mut sum = 0;
+        let mut output = |value| sum += value;
+        doubler().run_with(&mut input, &mut output).unwrap();
+
+        assert_eq!(sum, 6);
+    }
+
+    #[test]
+    fn channels_between_machines() {
+        let (to_first, first_input) = channel();
+        let (first_output, second_input) = channel();
+        let (mut second_output, outputs) = channel();
+
+        let first = thread::spawn(move || {
+            let mut first_input = first_input;
+            let mut first_output = first_output;
+            doubler().run_with(&mut first_input, &mut first_output)
+        });
+        let second = thread::spawn(move || {
+            let mut second_input = second_input;
+            doubler().run_with(&mut second_input, &mut second_output)
+        });
+
+        for value in 1..=3 {
+            to_first.send(value).unwrap();
+        }
+        to_first.send(0).unwrap();
+
+        first.join().unwrap().unwrap();
+        // The first machine never forwards the terminating zero.
+        assert!(matches!(
+            second.join().unwrap(),
+            Err(IntcodeError::MissingInput { position: 0, .. })
+        ));
+        assert_eq!(outputs.iter().collect::<Vec<_>>(), vec![4, 8, 12]);
+    }
+
+    #[test]
+    fn closed_output() {
+        let (mut output, receiver) = channel();
+        drop(receiver);
+
+        let mut program = doubler();
+        assert!(matches!(
+            program.run_with(&mut IterInput(vec![1].into_iter()), &mut output),
+            Err(IntcodeError::OutputClosed { position: 9, .. })
+        ));
+
+        // The value that was not accepted is emitted again on resume.
+        assert_eq!(program.position(), 9);
+        assert_eq!(program.resume().unwrap(), Status::Output(2));
+    }
+}
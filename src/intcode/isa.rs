@@ -0,0 +1,349 @@
+//! Instruction sets: which operations a machine accepts.
+//!
+//! Machines run the full instruction set of day 9 by default. Profiles of
+//! earlier days restrict it, so a program that was written for day 2 fails on
+//! the opcodes it was never meant to use. Custom operations can be registered
+//! on top of any profile.
+
+use super::{Operation, Word};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+/// How an instruction uses one of its parameters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParameterKind {
+    /// The parameter is read, in any mode.
+    Read,
+    /// The parameter is an address that is written to. It cannot be in
+    /// immediate mode.
+    Write,
+}
+
+/// What a custom instruction does once it computed its result.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action<W = i64> {
+    /// Continue with the next instruction.
+    Continue,
+    /// Write the value to the write parameter and continue. Operations
+    /// without a write parameter fail with `IntcodeError::MissingTarget`.
+    Write(W),
+    /// Jump to an address.
+    Jump(W),
+    /// Emit an output and continue.
+    Output(W),
+    Halt,
+}
+
+type Semantics<W> = dyn Fn(&[W]) -> Action<W> + Send + Sync;
+
+/// An operation that is not built into the machine.
+#[derive(Clone)]
+pub struct CustomOperation<W = i64> {
+    pub opcode: i64,
+    pub parameters: Vec<ParameterKind>,
+    /// Computes the action from the values of the read parameters, in order.
+    semantics: Arc<Semantics<W>>,
+}
+
+#[derive(Clone)]
+pub struct InstructionSet<W = i64> {
+    name: String,
+    builtins: Vec<Operation>,
+    custom: BTreeMap<i64, CustomOperation<W>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RegisterError {
+    /// Opcodes are two decimal digits and 99 is the halt, so custom opcodes
+    /// must be between 1 and 98.
+    InvalidOpcode(i64),
+    /// The opcode already names an operation of the instruction set.
+    Conflict(i64),
+    /// An operation can write to at most one parameter.
+    TooManyWrites,
+}
+
+const DAY02: [Operation; 3] = [Operation::Add, Operation::Multiply, Operation::Halt];
+
+const DAY05: [Operation; 9] = [
+    Operation::Add,
+    Operation::Multiply,
+    Operation::Input,
+    Operation::Output,
+    Operation::JumpIfTrue,
+    Operation::JumpIfFalse,
+    Operation::LessThan,
+    Operation::Equals,
+    Operation::Halt,
+];
+
+const FULL: [Operation; 10] = [
+    Operation::Add,
+    Operation::Multiply,
+    Operation::Input,
+    Operation::Output,
+    Operation::JumpIfTrue,
+    Operation::JumpIfFalse,
+    Operation::LessThan,
+    Operation::Equals,
+    Operation::AdjustRelativeBase,
+    Operation::Halt,
+];
+
+impl<W: Word> InstructionSet<W> {
+    fn with_builtins(name: &str, builtins: &[Operation]) -> InstructionSet<W> {
+        InstructionSet {
+            name: name.to_string(),
+            builtins: builtins.to_vec(),
+            custom: BTreeMap::new(),
+        }
+    }
+
+    /// Addition, multiplication and halt, as introduced on day 2.
+    pub fn day02() -> InstructionSet<W> {
+        InstructionSet::with_builtins("day02", &DAY02)
+    }
+
+    /// Everything but the relative base, as completed on day 5.
+    pub fn day05() -> InstructionSet<W> {
+        InstructionSet::with_builtins("day05", &DAY05)
+    }
+
+    /// Every built-in operation. Machines use it by default.
+    pub fn full() -> InstructionSet<W> {
+        InstructionSet::with_builtins("full", &FULL)
+    }
+
+    /// The profile with the given name: `day02`, `day05` or `full`.
+    pub fn profile(name: &str) -> Option<InstructionSet<W>> {
+        match name {
+            "day02" => Some(InstructionSet::day02()),
+            "day05" => Some(InstructionSet::day05()),
+            "full" => Some(InstructionSet::full()),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Adds an operation. `semantics` gets the values of the read parameters
+    /// in order. Operations are shown and assembled as `op` followed by their
+    /// opcode, e.g. `op42`.
+    pub fn register<F>(
+        &mut self,
+        opcode: i64,
+        parameters: &[ParameterKind],
+        semantics: F,
+    ) -> Result<(), RegisterError>
+    where
+        F: Fn(&[W]) -> Action<W> + Send + Sync + 'static,
+    {
+        if !(1..=98).contains(&opcode) {
+            return Err(RegisterError::InvalidOpcode(opcode));
+        }
+        if self.operation(opcode).is_some() {
+            return Err(RegisterError::Conflict(opcode));
+        }
+        let writes = parameters
+            .iter()
+            .filter(|kind| **kind == ParameterKind::Write)
+            .count();
+        if writes > 1 {
+            return Err(RegisterError::TooManyWrites);
+        }
+
+        self.custom.insert(
+            opcode,
+            CustomOperation {
+                opcode,
+                parameters: parameters.to_vec(),
+                semantics: Arc::new(semantics),
+            },
+        );
+        Ok(())
+    }
+
+    /// Whether the instruction set accepts an operation.
+    pub fn supports(&self, operation: Operation) -> bool {
+        match operation {
+            Operation::Custom { opcode, .. } => self.custom.contains_key(&opcode),
+            operation => self.builtins.contains(&operation),
+        }
+    }
+
+    /// The operation an opcode names, without parameter modes.
+    pub fn operation(&self, opcode: i64) -> Option<Operation> {
+        match Operation::from_opcode(opcode) {
+            Some(operation) if self.builtins.contains(&operation) => Some(operation),
+            _ => self.custom.get(&opcode).map(CustomOperation::operation),
+        }
+    }
+
+    pub fn custom(&self, opcode: i64) -> Option<&CustomOperation<W>> {
+        self.custom.get(&opcode)
+    }
+
+    /// Whether operations were registered on top of the profile.
+    pub(super) fn has_custom_operations(&self) -> bool {
+        !self.custom.is_empty()
+    }
+}
+
+impl<W> CustomOperation<W> {
+    fn operation(&self) -> Operation {
+        Operation::Custom {
+            opcode: self.opcode,
+            arity: self.parameters.len(),
+            target: self
+                .parameters
+                .iter()
+                .position(|kind| *kind == ParameterKind::Write),
+        }
+    }
+
+    /// Runs the operation on the values of its read parameters.
+    pub fn apply(&self, values: &[W]) -> Action<W> {
+        (self.semantics)(values)
+    }
+}
+
+impl<W> fmt::Debug for CustomOperation<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CustomOperation")
+            .field("opcode", &self.opcode)
+            .field("parameters", &self.parameters)
+            .finish()
+    }
+}
+
+impl<W> fmt::Debug for InstructionSet<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("InstructionSet")
+            .field("name", &self.name)
+            .field("builtins", &self.builtins)
+            .field("custom", &self.custom.values().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegisterError::InvalidOpcode(opcode) => {
+                write!(f, "Opcode {} is not between 1 and 98", opcode)
+            }
+            RegisterError::Conflict(opcode) => write!(f, "Opcode {} is already taken", opcode),
+            RegisterError::TooManyWrites => {
+                write!(f, "Operations can write to at most one parameter")
+            }
+        }
+    }
+}
+
+impl Error for RegisterError {}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Intcode, IntcodeError, Status};
+    use super::*;
+
+    #[test]
+    fn profiles_reject_later_opcodes() {
+        let mut program = Intcode::new(vec![1101, 2, 3, 0, 4, 0, 99]);
+        program.set_instruction_set(InstructionSet::day02());
+
+        assert_eq!(program.step().unwrap(), Status::Running);
+        assert!(matches!(
+            program.step(),
+            Err(IntcodeError::UnknownOperation {
+                position: 4,
+                opcode: 4
+            })
+        ));
+
+        let mut program = Intcode::new(vec![109, 1, 99]);
+        program.set_instruction_set(InstructionSet::profile("day05").unwrap());
+        assert!(program.run(vec![]).is_err());
+        program.set_instruction_set(InstructionSet::profile("full").unwrap());
+        assert!(program.run(vec![]).is_ok());
+
+        assert!(InstructionSet::<i64>::profile("day11").is_none());
+    }
+
+    #[test]
+    fn custom_operations() {
+        let mut isa = InstructionSet::full();
+        isa.register(
+            20,
+            &[
+                ParameterKind::Read,
+                ParameterKind::Read,
+                ParameterKind::Write,
+            ],
+            |values: &[i64]| Action::Write(values[0].max(values[1])),
+        )
+        .unwrap();
+        isa.register(21, &[ParameterKind::Read], |values: &[i64]| {
+            Action::Output(values[0] * values[0])
+        })
+        .unwrap();
+
+        // Reads two numbers and outputs the square of the larger one.
+        let mut program = Intcode::new(vec![3, 11, 3, 12, 20, 11, 12, 11, 21, 11, 99, 0, 0]);
+        program.set_instruction_set(isa);
+
+        assert_eq!(program.run(vec![3, -7]).unwrap(), vec![9]);
+    }
+
+    #[test]
+    fn write_without_target() {
+        let mut isa = InstructionSet::full();
+        isa.register(20, &[ParameterKind::Read], |values: &[i64]| {
+            Action::Write(values[0])
+        })
+        .unwrap();
+
+        let mut program = Intcode::new(vec![1101, 1, 1, 7, 120, 5, 99, 0]);
+        program.set_instruction_set(isa);
+
+        assert!(matches!(
+            program.run(vec![]),
+            Err(IntcodeError::MissingTarget {
+                position: 4,
+                opcode: 120
+            })
+        ));
+        assert_eq!(program.position(), 4);
+    }
+
+    #[test]
+    fn invalid_registrations() {
+        let mut isa = InstructionSet::<i64>::day02();
+        let nop = |_: &[i64]| Action::Continue;
+
+        assert_eq!(isa.register(1, &[], nop), Err(RegisterError::Conflict(1)));
+        assert_eq!(
+            isa.register(99, &[], nop),
+            Err(RegisterError::InvalidOpcode(99))
+        );
+        assert_eq!(
+            isa.register(5, &[ParameterKind::Write; 2], nop),
+            Err(RegisterError::TooManyWrites)
+        );
+
+        // Opcodes of later days are free in earlier profiles.
+        assert_eq!(isa.register(5, &[], nop), Ok(()));
+        assert_eq!(
+            isa.operation(5),
+            Some(Operation::Custom {
+                opcode: 5,
+                arity: 0,
+                target: None
+            })
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use std::time::{Duration, Instant};
+
+/// How often the clock is read, in instructions. Reading it on every
+/// instruction would slow the machine down noticeably.
+const CLOCK_INTERVAL: u64 = 1024;
+
+/// Limits on the resources a machine may use, see `Intcode::set_limits`.
+/// Every limit that is `None` is unlimited.
+///
+/// A machine that reaches a limit stops before the instruction that would
+/// exceed it, with an error for that limit. Nothing of the instruction is
+/// executed, so the machine can be inspected, saved or resumed with new
+/// limits.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Limits {
+    /// The number of instructions the machine may execute.
+    pub instructions: Option<u64>,
+    /// How long the machine may run. The clock is only read every few
+    /// instructions, so it may overrun the limit by a little.
+    pub time: Option<Duration>,
+    /// Writes must go to addresses below this limit. Reading beyond it is
+    /// fine, since unwritten memory takes no space.
+    pub memory: Option<usize>,
+    /// The number of outputs the program may emit.
+    pub outputs: Option<usize>,
+}
+
+/// A limit that was reached, before it is attributed to an instruction.
+pub(super) enum Exceeded {
+    Instructions(u64),
+    Time(Duration),
+}
+
+/// The limits of a machine and what it used up so far.
+#[derive(Clone, Debug)]
+pub(super) struct Budget {
+    pub(super) limits: Limits,
+    pub(super) executed: u64,
+    pub(super) outputs: usize,
+    started: Instant,
+}
+
+impl Budget {
+    pub(super) fn new(limits: Limits) -> Budget {
+        Budget {
+            limits,
+            executed: 0,
+            outputs: 0,
+            started: Instant::now(),
+        }
+    }
+
+    /// Forgets the resources used so far, and starts the clock again.
+    pub(super) fn restart(&mut self) {
+        *self = Budget::new(self.limits.clone());
+    }
+
+    /// Checks the limits that do not depend on the next instruction.
+    pub(super) fn check(&self) -> Result<(), Exceeded> {
+        if let Some(instructions) = self.limits.instructions {
+            if self.executed >= instructions {
+                return Err(Exceeded::Instructions(self.executed));
+            }
+        }
+
+        if let Some(time) = self.limits.time {
+            if self.executed.is_multiple_of(CLOCK_INTERVAL) {
+                let elapsed = self.started.elapsed();
+                if elapsed >= time {
+                    return Err(Exceeded::Time(elapsed));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn may_write(&self, address: usize) -> bool {
+        self.limits.memory.is_none_or(|memory| address < memory)
+    }
+
+    pub(super) fn may_output(&self) -> bool {
+        self.limits
+            .outputs
+            .is_none_or(|outputs| self.outputs < outputs)
+    }
+}
@@ -0,0 +1,254 @@
+use super::Word;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Addresses below this limit are stored in contiguous pages, which are
+/// allocated on demand. Anything above goes into a sparse map instead, so a
+/// single write to a huge address does not allocate gigabytes.
+const DENSE_LIMIT: usize = 1 << 16;
+
+const PAGE_BITS: usize = 10;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+
+type Page<W> = Arc<Vec<W>>;
+
+/// The memory of an Intcode machine.
+///
+/// Memory is unbounded: every address that was never written reads as zero.
+///
+/// Cloning memory is cheap: clones share their pages until one of them writes
+/// to a page, which then copies just that page. The sparse map is shared the
+/// same way, but copied as a whole.
+///
+/// The page written last is the hot page. It is owned by this memory alone,
+/// so further writes to it need no reference counting. Cloning copies it.
+#[derive(Clone, Debug)]
+pub struct Memory<W> {
+    pages: Arc<Vec<Page<W>>>,
+    /// The contents of the hot page, empty if there is none. Its entry in
+    /// `pages` is stale.
+    hot: Vec<W>,
+    /// The first address of the hot page.
+    hot_start: usize,
+    /// The number of cells up to and including the highest address in the
+    /// pages that was ever loaded or written.
+    dense_len: usize,
+    sparse: Arc<HashMap<usize, W>>,
+}
+
+impl<W: Word> Memory<W> {
+    pub fn new(data: Vec<W>) -> Memory<W> {
+        let dense_len = data.len();
+        let pages = data
+            .chunks(PAGE_SIZE)
+            .map(|chunk| {
+                let mut page = chunk.to_vec();
+                page.resize(PAGE_SIZE, W::zero());
+                Arc::new(page)
+            })
+            .collect();
+
+        Memory {
+            pages: Arc::new(pages),
+            hot: vec![],
+            hot_start: 0,
+            dense_len,
+            sparse: Arc::new(HashMap::new()),
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, address: usize) -> W {
+        // A single bounds check finds the hot page, and fails if there is none.
+        if let Some(value) = self.hot.get(address.wrapping_sub(self.hot_start)) {
+            return value.clone();
+        }
+
+        let index = address >> PAGE_BITS;
+        if let Some(page) = self.pages.get(index) {
+            return page[address & (PAGE_SIZE - 1)].clone();
+        }
+
+        self.get_sparse(address)
+    }
+
+    #[cold]
+    fn get_sparse(&self, address: usize) -> W {
+        self.sparse.get(&address).cloned().unwrap_or_else(W::zero)
+    }
+
+    #[inline]
+    pub fn set(&mut self, address: usize, value: W) {
+        match self.hot.get_mut(address.wrapping_sub(self.hot_start)) {
+            Some(cell) => *cell = value,
+            None => return self.set_cold(address, value),
+        }
+        self.dense_len = self.dense_len.max(address + 1);
+    }
+
+    /// Writes outside the hot page.
+    #[cold]
+    fn set_cold(&mut self, address: usize, value: W) {
+        let index = address >> PAGE_BITS;
+        if index >= self.pages.len() && address >= DENSE_LIMIT {
+            Arc::make_mut(&mut self.sparse).insert(address, value);
+            return;
+        }
+
+        self.heat(index);
+        self.hot[address & (PAGE_SIZE - 1)] = value;
+        self.dense_len = self.dense_len.max(address + 1);
+    }
+
+    fn hot_index(&self) -> Option<usize> {
+        if self.hot.is_empty() {
+            None
+        } else {
+            Some(self.hot_start >> PAGE_BITS)
+        }
+    }
+
+    /// Makes the page at `index` the hot page, after writing the current hot
+    /// page back.
+    fn heat(&mut self, index: usize) {
+        let hot_index = self.hot_index();
+        let pages = Arc::make_mut(&mut self.pages);
+
+        if let Some(hot_index) = hot_index {
+            *Arc::make_mut(&mut pages[hot_index]) = std::mem::take(&mut self.hot);
+        }
+        if index >= pages.len() {
+            pages.resize_with(index + 1, || Arc::new(vec![W::zero(); PAGE_SIZE]));
+        }
+
+        self.hot = std::mem::take(Arc::make_mut(&mut pages[index]));
+        self.hot_start = index << PAGE_BITS;
+    }
+
+    /// The number of cells up to and including the highest address that was
+    /// ever loaded or written.
+    pub fn len(&self) -> usize {
+        let sparse_len = self.sparse.keys().max().map_or(0, |address| address + 1);
+        self.dense_len.max(sparse_len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every cell that was ever loaded or written, in order of its address.
+    pub fn cells(&self) -> impl Iterator<Item = (usize, &W)> {
+        let mut sparse: Vec<(usize, &W)> = self
+            .sparse
+            .iter()
+            .map(|(address, value)| (*address, value))
+            .collect();
+        sparse.sort_by_key(|(address, _)| *address);
+
+        (0..self.dense_len)
+            .map(move |address| {
+                let index = address >> PAGE_BITS;
+                let page = if self.hot_index() == Some(index) {
+                    &self.hot
+                } else {
+                    &self.pages[index]
+                };
+
+                (address, &page[address & (PAGE_SIZE - 1)])
+            })
+            .chain(sparse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unwritten_cells_read_zero() {
+        let memory = Memory::new(vec![1, 2, 3]);
+        assert_eq!(memory.get(2), 3);
+        assert_eq!(memory.get(3), 0);
+        assert_eq!(memory.get(usize::MAX), 0);
+    }
+
+    #[test]
+    fn cells_in_address_order() {
+        let mut memory = Memory::new(vec![1, 2]);
+        memory.set(1 << 20, 4);
+        memory.set(1 << 18, 3);
+
+        let cells: Vec<(usize, i64)> = memory.cells().map(|(a, v)| (a, *v)).collect();
+        assert_eq!(cells, vec![(0, 1), (1, 2), (1 << 18, 3), (1 << 20, 4)]);
+    }
+
+    #[test]
+    fn writes_grow_dense_memory() {
+        let mut memory = Memory::new(vec![1, 2, 3]);
+        memory.set(10, 4);
+
+        assert_eq!(memory.dense_len, 11);
+        assert_eq!(memory.get(9), 0);
+        assert_eq!(memory.get(10), 4);
+        assert_eq!(memory.len(), 11);
+    }
+
+    #[test]
+    fn large_addresses_are_sparse() {
+        let mut memory = Memory::new(vec![1, 2, 3]);
+        memory.set(1 << 40, 5);
+
+        assert_eq!(memory.dense_len, 3);
+        assert_eq!(memory.pages.len(), 1);
+        assert_eq!(memory.get(1 << 40), 5);
+        assert_eq!(memory.len(), (1 << 40) + 1);
+    }
+
+    #[test]
+    fn clones_share_pages_until_written() {
+        let shared = |a: &Memory<i64>, b: &Memory<i64>| {
+            a.pages
+                .iter()
+                .zip(b.pages.iter())
+                .filter(|(a, b)| Arc::ptr_eq(a, b))
+                .count()
+        };
+
+        let original = Memory::new((0..3000).collect());
+        let mut fork = original.clone();
+        assert!(Arc::ptr_eq(&original.pages, &fork.pages));
+
+        fork.set(1500, -1);
+        fork.set(1 << 20, -2);
+        assert_eq!(shared(&original, &fork), 2);
+        assert_eq!(original.get(1500), 1500);
+        assert_eq!(original.get(1 << 20), 0);
+        assert_eq!(fork.get(1500), -1);
+        assert_eq!(fork.get(1 << 20), -2);
+        assert_eq!(original.len(), 3000);
+        assert_eq!(fork.len(), (1 << 20) + 1);
+    }
+
+    #[test]
+    fn hot_page_is_written_back() {
+        let mut memory = Memory::new(vec![0; 3000]);
+        memory.set(10, 1);
+        let fork = memory.clone();
+        memory.set(2500, 2);
+        memory.set(11, 3);
+
+        assert_eq!(memory.hot_index(), Some(0));
+        assert_eq!(
+            (memory.get(10), memory.get(11), memory.get(2500)),
+            (1, 3, 2)
+        );
+        assert_eq!((fork.get(10), fork.get(11), fork.get(2500)), (1, 0, 0));
+
+        let cells: Vec<(usize, i64)> = memory
+            .cells()
+            .filter(|(_, value)| **value != 0)
+            .map(|(address, value)| (address, *value))
+            .collect();
+        assert_eq!(cells, vec![(10, 1), (11, 3), (2500, 2)]);
+    }
+}
@@ -1,196 +1,1168 @@
+pub mod ascii;
+pub mod assemble;
+mod cache;
+pub mod canvas;
+pub mod compile;
+mod cycle;
+pub mod debugger;
+pub mod disassemble;
+mod error;
+pub mod flow;
+mod instruction;
+mod io;
+pub mod isa;
+mod limits;
+mod memory;
+pub mod network;
+pub mod search;
+pub mod snapshot;
+pub mod symbolic;
+pub mod trace;
+mod word;
+
+pub use self::error::IntcodeError;
+pub use self::instruction::{Instruction, Operation, Parameter};
+pub use self::io::{InputSource, IterInput, OutputSink};
+pub use self::limits::Limits;
+pub use self::memory::Memory;
+pub use self::word::Word;
+
+use self::cache::{DecodeCache, CACHE_LIMIT};
+use self::cycle::{Cycles, Loop};
+use self::instruction::Decoded;
+use self::isa::{Action, InstructionSet};
+use self::limits::{Budget, Exceeded};
+use self::trace::{Effects, Event, MemoryWrite, Operand, Tracer};
+
+use std::collections::VecDeque;
 use std::fs::File;
 use std::io::prelude::*;
+use std::sync::Arc;
 
 #[derive(Clone)]
-pub struct Intcode {
-    data: Vec<i32>,
+pub struct Intcode<W = i64> {
+    memory: Memory<W>,
+    /// The number of words of the program that was loaded. Running past them
+    /// ends the program.
+    program_len: usize,
     position: usize,
+    relative_base: W,
+    inputs: VecDeque<W>,
+    /// Outputs collected by `pump` that were not taken yet.
+    outputs: VecDeque<W>,
+    /// The memory writes of the last instruction, if it was traced.
+    writes: Vec<MemoryWrite<W>>,
+    /// Whether `write_at` records into `writes`.
+    tracing: bool,
+    /// The address the last instruction wrote to, for the decode cache.
+    written: Option<usize>,
+    /// The resolved parameters of the last instruction, kept to reuse their
+    /// allocation.
+    operands: Vec<Operand<W>>,
+    cache: Option<DecodeCache<W>>,
+    /// The instruction set, if the machine does not run the full one.
+    isa: Option<Arc<InstructionSet<W>>>,
+    budget: Option<Box<Budget>>,
+    cycles: Option<Box<Cycles<W>>>,
+    /// The hash of the memory, kept up to date while cycles are detected.
+    memory_hash: Option<u64>,
 }
 
+/// The state a program is left in after executing one or more instructions.
 #[derive(Debug, PartialEq)]
-enum Parameter {
-    Position(i32),
-    Immediate(i32),
+pub enum Status<W = i64> {
+    /// The instruction was executed and the program can continue.
+    Running,
+    /// The program wants to read an input but none is queued. The
+    /// instruction pointer still points at the input instruction, so
+    /// execution resumes there once an input was provided.
+    NeedsInput,
+    /// The program emitted a value.
+    Output(W),
+    /// The program reached a halt instruction.
+    Halted,
 }
 
-#[derive(Debug, PartialEq)]
-enum Operation {
-    Add,
-    Multiply,
-    Input,
-    Output,
-    JumpIfTrue,
-    JumpIfFalse,
-    LessThan,
-    Equals,
-    Halt,
+/// A failure during execution, before it is attributed to an instruction.
+enum Fault<W> {
+    UnknownOperation,
+    UnknownMode(i64),
+    ImmediateTarget,
+    MissingInput,
+    OutputClosed,
+    InvalidAddress(W),
+    MalformedInstruction,
+    Overflow,
+    MissingTarget,
+    Limit(Exceeded),
+    MemoryLimit(usize),
+    OutputLimit,
+    Loop(Loop),
 }
 
 impl Intcode {
-    pub fn new(data: Vec<i32>) -> Intcode {
-        Intcode { data, position: 0 }
+    pub fn new(data: Vec<i64>) -> Intcode {
+        Intcode::with_words(data)
     }
 
-    pub fn from_file(filename: &str) -> Intcode {
-        let mut io = File::open(filename).expect("File not opened.");
+    pub fn from_file(filename: &str) -> Result<Intcode, IntcodeError> {
+        Intcode::load_file(filename)
+    }
+}
+
+impl<W: Word> Intcode<W> {
+    /// Creates a machine with an arbitrary word type, see `Word`.
+    pub fn with_words(data: Vec<W>) -> Intcode<W> {
+        Intcode {
+            program_len: data.len(),
+            memory: Memory::new(data),
+            position: 0,
+            relative_base: W::zero(),
+            inputs: VecDeque::new(),
+            outputs: VecDeque::new(),
+            writes: vec![],
+            tracing: false,
+            written: None,
+            operands: vec![],
+            cache: None,
+            isa: None,
+            budget: None,
+            cycles: None,
+            memory_hash: None,
+        }
+    }
+
+    /// Loads a program with an arbitrary word type from a file.
+    pub fn load_file(filename: &str) -> Result<Intcode<W>, IntcodeError<W>> {
+        let mut io = File::open(filename)?;
         let mut contents = String::new();
 
-        io.read_to_string(&mut contents)
-            .expect("Failed to read file.");
+        io.read_to_string(&mut contents)?;
 
         let data = contents
             .trim_end()
-            .split(",")
-            .map(|a| a.parse().unwrap())
-            .collect();
-        Intcode { data, position: 0 }
+            .split(',')
+            .enumerate()
+            .map(|(index, token)| {
+                token.trim().parse().map_err(|_| IntcodeError::Parse {
+                    index,
+                    token: token.to_string(),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Intcode::with_words(data))
+    }
+
+    /// Caches decoded instructions from now on, which makes execution a lot
+    /// faster. Entries are invalidated when the program writes to them, so
+    /// self-modifying code keeps working. Clones share the cache until one of
+    /// them changes it.
+    pub fn enable_decode_cache(&mut self) {
+        if self.cache.is_none() {
+            self.cache = Some(DecodeCache::new(self.memory.len()));
+        }
+    }
+
+    /// Restricts or extends the operations the machine accepts, see
+    /// `InstructionSet`.
+    pub fn set_instruction_set(&mut self, isa: InstructionSet<W>) {
+        self.isa = Some(Arc::new(isa));
+        if self.cache.is_some() {
+            self.cache = Some(DecodeCache::new(self.memory.len()));
+        }
+    }
+
+    /// The instruction set of the machine, or `None` if it runs the full
+    /// instruction set.
+    pub fn instruction_set(&self) -> Option<&InstructionSet<W>> {
+        self.isa.as_deref()
+    }
+
+    /// Limits the resources the machine may use from now on, see `Limits`.
+    /// The instructions, outputs and time used so far are forgotten, so this
+    /// also resumes a machine that stopped at a limit. Clones count on from
+    /// the resources used so far, but machines that start from scratch, like
+    /// the one of `run_patched`, count from zero again.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.budget = Some(Box::new(Budget::new(limits)));
+    }
+
+    pub fn limits(&self) -> Option<&Limits> {
+        self.budget.as_ref().map(|budget| &budget.limits)
+    }
+
+    /// Forgets the resources used so far, for a copy of the machine that
+    /// starts from scratch.
+    fn restart_limits(&mut self) {
+        if let Some(budget) = &mut self.budget {
+            budget.restart();
+        }
+    }
+
+    /// Stops the machine with `IntcodeError::InfiniteLoop` once it comes back
+    /// to a state it was in before without consuming an input in between,
+    /// instead of letting it repeat the same instructions forever. Keeping
+    /// track of the states slows execution down a little.
+    pub fn enable_cycle_detection(&mut self) {
+        if self.cycles.is_none() {
+            self.memory_hash = Some(cycle::memory_hash(&self.memory));
+            self.cycles = Some(Box::new(Cycles::new()));
+        }
+    }
+
+    /// The instruction pointer.
+    pub fn position(&self) -> usize {
+        self.position
     }
 
-    fn read_at(&self, position: u32) -> i32 {
-        self.data[position as usize]
+    pub fn relative_base(&self) -> &W {
+        &self.relative_base
     }
 
-    fn write_at(&mut self, position: u32, value: i32) {
-        self.data[position as usize] = value;
+    pub fn memory(&self) -> &Memory<W> {
+        &self.memory
     }
 
-    fn read(&mut self, count: u32) -> &[i32] {
-        let ints = &self.data[self.position..self.position + (count as usize)];
-        self.position = self.position + count as usize;
+    fn read_at(&self, position: usize) -> W {
+        self.memory.get(position)
+    }
 
-        ints
+    fn write_at(&mut self, position: usize, value: W) {
+        self.written = Some(position);
+        if self.tracing {
+            self.writes.push(MemoryWrite {
+                address: position,
+                old: self.read_at(position),
+                new: value.clone(),
+            });
+        }
+        self.rehash(position, &value);
+        self.memory.set(position, value);
     }
 
     fn jump(&mut self, position: usize) {
         self.position = position;
     }
 
-    fn get_input(&self, parameter: &Parameter) -> i32 {
+    fn jump_to(&mut self, target: W) -> Result<(), Fault<W>> {
+        let position = target.to_address().ok_or(Fault::InvalidAddress(target))?;
+        self.jump(position);
+        Ok(())
+    }
+
+    fn get_input(&self, parameter: &Parameter<W>) -> Result<W, Fault<W>> {
         match parameter {
-            Parameter::Immediate(value) => *value,
-            Parameter::Position(value) => self.read_at(*value as u32),
+            Parameter::Immediate(value) => Ok(value.clone()),
+            Parameter::Position(_) | Parameter::Relative(_) => {
+                Ok(self.read_at(self.get_address(parameter)?))
+            }
         }
     }
 
-    fn get_target(&self, parameter: &Parameter) -> u32 {
-        match parameter {
-            Parameter::Immediate(_) => panic!("Target parameter should be in position mode!"),
-            Parameter::Position(value) => *value as u32,
+    fn get_target(&self, parameter: &Parameter<W>) -> Result<usize, Fault<W>> {
+        let address = self.get_address(parameter)?;
+
+        match &self.budget {
+            Some(budget) if !budget.may_write(address) => Err(Fault::MemoryLimit(address)),
+            _ => Ok(address),
         }
     }
 
-    pub fn read_one(&mut self) -> i32 {
-        self.read(1)[0]
+    fn may_output(&self) -> bool {
+        self.budget
+            .as_ref()
+            .is_none_or(|budget| budget.may_output())
     }
 
-    pub fn run(&mut self, inputs: Vec<i32>) -> Vec<i32> {
-        let mut inputs = inputs.iter();
-        let mut outputs = vec![];
+    fn get_address(&self, parameter: &Parameter<W>) -> Result<usize, Fault<W>> {
+        let address = match parameter {
+            Parameter::Immediate(_) => return Err(Fault::ImmediateTarget),
+            Parameter::Position(value) => value.clone(),
+            Parameter::Relative(offset) => self
+                .relative_base
+                .checked_add(offset)
+                .ok_or(Fault::Overflow)?,
+        };
 
-        loop {
-            let (operation, parameters) = self.next();
+        address.to_address().ok_or(Fault::InvalidAddress(address))
+    }
+
+    fn error(&self, position: usize, fault: Fault<W>) -> IntcodeError<W> {
+        let opcode = self.read_at(position);
+
+        match fault {
+            // Memory past the program reads as zero, which is no operation.
+            Fault::UnknownOperation if position >= self.program_len => {
+                IntcodeError::EndOfProgram { position, opcode }
+            }
+            Fault::UnknownOperation => IntcodeError::UnknownOperation { position, opcode },
+            Fault::UnknownMode(mode) => IntcodeError::UnknownMode {
+                position,
+                opcode,
+                mode,
+            },
+            Fault::ImmediateTarget => IntcodeError::ImmediateTarget { position, opcode },
+            Fault::MissingInput => IntcodeError::MissingInput { position, opcode },
+            Fault::OutputClosed => IntcodeError::OutputClosed { position, opcode },
+            Fault::InvalidAddress(address) => IntcodeError::InvalidAddress {
+                position,
+                opcode,
+                address,
+            },
+            Fault::MalformedInstruction => IntcodeError::MalformedInstruction { position, opcode },
+            Fault::Overflow => IntcodeError::Overflow { position, opcode },
+            Fault::MissingTarget => IntcodeError::MissingTarget { position, opcode },
+            Fault::Limit(Exceeded::Instructions(executed)) => IntcodeError::InstructionLimit {
+                position,
+                opcode,
+                executed,
+            },
+            Fault::Limit(Exceeded::Time(elapsed)) => IntcodeError::Timeout {
+                position,
+                opcode,
+                elapsed,
+            },
+            Fault::MemoryLimit(address) => IntcodeError::MemoryLimit {
+                position,
+                opcode,
+                address,
+            },
+            Fault::OutputLimit => IntcodeError::OutputLimit {
+                position,
+                opcode,
+                outputs: self.budget.as_ref().map_or(0, |budget| budget.outputs),
+            },
+            Fault::Loop(Loop { entry, period }) => IntcodeError::InfiniteLoop {
+                position,
+                opcode,
+                entry,
+                period,
+            },
+        }
+    }
+
+    /// Overwrites a word of memory, e.g. to set the parameters of a program
+    /// before it runs.
+    pub fn patch(&mut self, address: usize, value: W) {
+        if let Some(cache) = &mut self.cache {
+            cache.invalidate(address);
+        }
+        self.rehash(address, &value);
+        self.memory.set(address, value);
+    }
+
+    /// Runs a copy of the program with some words of memory overwritten, and
+    /// returns the word at address 0 once it halts.
+    ///
+    /// This is how programs without inputs or outputs, like the gravity assist
+    /// program of day 2, communicate.
+    pub fn run_patched(&self, patches: &[(usize, W)]) -> Result<W, IntcodeError<W>> {
+        let mut machine = self.clone();
+        machine.restart_limits();
+        for (address, value) in patches {
+            machine.patch(*address, value.clone());
+        }
 
-            match operation {
-                Operation::Add => {
-                    assert_eq!(parameters.len(), 3);
+        machine.run(vec![])?;
+        Ok(machine.read_at(0))
+    }
+
+    pub fn read_one(&mut self) -> W {
+        let value = self.read_at(self.position);
+        self.position += 1;
+
+        value
+    }
+
+    /// Queues a value to be consumed by the next input instruction.
+    pub fn provide_input(&mut self, value: W) {
+        self.inputs.push_back(value);
+    }
+
+    /// Executes a single instruction.
+    ///
+    /// If the instruction fails, the machine is left unchanged and still
+    /// points at the failing instruction.
+    pub fn step(&mut self) -> Result<Status<W>, IntcodeError<W>> {
+        self.step_traced(&mut ())
+    }
 
-                    let a = self.get_input(&parameters[0]);
-                    let b = self.get_input(&parameters[1]);
-                    self.write_at(self.get_target(&parameters[2]), a + b);
+    /// Executes a single instruction like `step` and reports it to `tracer`.
+    pub fn step_traced<T>(&mut self, tracer: &mut T) -> Result<Status<W>, IntcodeError<W>>
+    where
+        T: Tracer<W> + ?Sized,
+    {
+        let start = self.position;
+
+        if let Some(budget) = &self.budget {
+            if let Err(exceeded) = budget.check() {
+                return Err(self.error(start, Fault::Limit(exceeded)));
+            }
+        }
+        if let Some(found) = self.watch_cycles() {
+            return Err(self.error(start, Fault::Loop(found)));
+        }
+
+        let inputs = self.inputs.len();
+        let status = self.execute(start, tracer).map_err(|fault| {
+            self.jump(start);
+            self.error(start, fault)
+        })?;
+        if status == Status::NeedsInput || self.inputs.len() < inputs {
+            self.forget_cycles();
+        }
+
+        if let Some(budget) = &mut self.budget {
+            match status {
+                Status::NeedsInput => {}
+                Status::Output(_) => {
+                    budget.executed += 1;
+                    budget.outputs += 1;
                 }
-                Operation::Multiply => {
-                    assert_eq!(parameters.len(), 3);
+                _ => budget.executed += 1,
+            }
+        }
+
+        Ok(status)
+    }
+
+    fn execute<T>(&mut self, start: usize, tracer: &mut T) -> Result<Status<W>, Fault<W>>
+    where
+        T: Tracer<W> + ?Sized,
+    {
+        // The cache is taken out of the machine while an instruction borrows
+        // from it.
+        match self.cache.take() {
+            Some(mut cache) => {
+                let status = self.execute_cached(&mut cache, start, tracer);
+                self.cache = Some(cache);
+                status
+            }
+            None => {
+                let decoded = instruction::decode(&self.memory, start, self.isa.as_deref())?;
+                self.execute_decoded(start, &decoded, tracer)
+            }
+        }
+    }
+
+    /// Executes the instruction at `start` with the decode cache, which was
+    /// taken out of the machine, and invalidates the cell it wrote.
+    fn execute_cached<T>(
+        &mut self,
+        cache: &mut DecodeCache<W>,
+        start: usize,
+        tracer: &mut T,
+    ) -> Result<Status<W>, Fault<W>>
+    where
+        T: Tracer<W> + ?Sized,
+    {
+        if start >= CACHE_LIMIT {
+            let decoded = instruction::decode(&self.memory, start, self.isa.as_deref())?;
+            return self.execute_decoded(start, &decoded, tracer);
+        }
 
-                    let a = self.get_input(&parameters[0]);
-                    let b = self.get_input(&parameters[1]);
-                    self.write_at(self.get_target(&parameters[2]), a * b);
+        let uncached;
+        let decoded = match cache.get(start) {
+            Some(decoded) => decoded,
+            None => {
+                if cache.admit(start) {
+                    let decoded = instruction::decode(&self.memory, start, self.isa.as_deref())?;
+                    cache.insert(start, decoded)
+                } else {
+                    uncached = instruction::decode(&self.memory, start, self.isa.as_deref())?;
+                    &uncached
                 }
-                Operation::Input => {
-                    assert_eq!(parameters.len(), 1);
-                    if let Some(input) = inputs.next() {
-                        self.write_at(self.get_target(&parameters[0]), *input);
-                    } else {
-                        panic!("Too few inputs provided!");
-                    }
+            }
+        };
+
+        self.written = None;
+        let status = self.execute_decoded(start, decoded, tracer);
+
+        if let Some(address) = self.written {
+            cache.invalidate(address);
+        }
+        status
+    }
+
+    fn execute_decoded<T>(
+        &mut self,
+        start: usize,
+        decoded: &Decoded<W>,
+        tracer: &mut T,
+    ) -> Result<Status<W>, Fault<W>>
+    where
+        T: Tracer<W> + ?Sized,
+    {
+        self.position += decoded.size();
+
+        if !tracer.is_enabled() {
+            return self.apply(start, decoded.operation, decoded.parameters());
+        }
+
+        let instruction = &decoded.to_instruction();
+        let mut operands = std::mem::take(&mut self.operands);
+        let status = self
+            .resolve(instruction, &mut operands)
+            .and_then(|()| self.trace(start, instruction, &operands, tracer));
+        self.operands = operands;
+
+        status
+    }
+
+    /// Applies the addressing modes of the parameters of an instruction.
+    fn resolve(
+        &self,
+        instruction: &Instruction<W>,
+        operands: &mut Vec<Operand<W>>,
+    ) -> Result<(), Fault<W>> {
+        let target = instruction.operation.target();
+        operands.clear();
+
+        for (index, parameter) in instruction.parameters.iter().enumerate() {
+            if target == Some(index) {
+                operands.push(Operand::Address(self.get_target(parameter)?));
+            } else {
+                operands.push(Operand::Value(self.get_input(parameter)?));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn trace<T>(
+        &mut self,
+        start: usize,
+        instruction: &Instruction<W>,
+        operands: &[Operand<W>],
+        tracer: &mut T,
+    ) -> Result<Status<W>, Fault<W>>
+    where
+        T: Tracer<W> + ?Sized,
+    {
+        let relative_base = self.relative_base.clone();
+        let event = Event {
+            position: start,
+            instruction,
+            operands,
+            relative_base: &relative_base,
+        };
+
+        tracer.before(&event);
+        self.writes.clear();
+        self.tracing = true;
+        let status = self.apply(start, instruction.operation, &instruction.parameters);
+        self.tracing = false;
+        let status = status?;
+
+        tracer.after(
+            &event,
+            &Effects {
+                writes: &self.writes,
+                position: self.position,
+                status: &status,
+            },
+        );
+
+        Ok(status)
+    }
+
+    fn apply(
+        &mut self,
+        start: usize,
+        operation: Operation,
+        parameters: &[Parameter<W>],
+    ) -> Result<Status<W>, Fault<W>> {
+        if parameters.len() != operation.arity() {
+            return Err(Fault::MalformedInstruction);
+        }
+
+        let value = |index: usize| self.get_input(&parameters[index]);
+        let target = |index: usize| self.get_target(&parameters[index]);
+
+        match operation {
+            Operation::Add => {
+                let result = value(0)?.checked_add(&value(1)?).ok_or(Fault::Overflow)?;
+                let target = target(2)?;
+                self.write_at(target, result);
+            }
+            Operation::Multiply => {
+                let result = value(0)?.checked_mul(&value(1)?).ok_or(Fault::Overflow)?;
+                let target = target(2)?;
+                self.write_at(target, result);
+            }
+            Operation::Input => {
+                let target = target(0)?;
+                if let Some(input) = self.inputs.pop_front() {
+                    self.write_at(target, input);
+                } else {
+                    self.jump(start);
+                    return Ok(Status::NeedsInput);
+                }
+            }
+            Operation::Output => {
+                let output = value(0)?;
+                if !self.may_output() {
+                    return Err(Fault::OutputLimit);
+                }
+                return Ok(Status::Output(output));
+            }
+            Operation::JumpIfTrue => {
+                if !value(0)?.is_zero() {
+                    let position = value(1)?;
+                    self.jump_to(position)?;
+                }
+            }
+            Operation::JumpIfFalse => {
+                if value(0)?.is_zero() {
+                    let position = value(1)?;
+                    self.jump_to(position)?;
                 }
-                Operation::Output => {
-                    assert_eq!(parameters.len(), 1);
-                    outputs.push(self.get_input(&parameters[0]));
+            }
+            Operation::LessThan => {
+                let result = if value(0)? < value(1)? {
+                    W::one()
+                } else {
+                    W::zero()
+                };
+                let target = target(2)?;
+                self.write_at(target, result);
+            }
+            Operation::Equals => {
+                let result = if value(0)? == value(1)? {
+                    W::one()
+                } else {
+                    W::zero()
+                };
+                let target = target(2)?;
+                self.write_at(target, result);
+            }
+            Operation::AdjustRelativeBase => {
+                self.relative_base = self
+                    .relative_base
+                    .checked_add(&value(0)?)
+                    .ok_or(Fault::Overflow)?;
+            }
+            Operation::Halt => {
+                // Stay on the halt instruction, so stepping again halts again.
+                self.jump(start);
+                return Ok(Status::Halted);
+            }
+            Operation::Custom { opcode, target, .. } => {
+                return self.apply_custom(start, opcode, target, parameters);
+            }
+        }
+
+        Ok(Status::Running)
+    }
+
+    fn apply_custom(
+        &mut self,
+        start: usize,
+        opcode: i64,
+        target: Option<usize>,
+        parameters: &[Parameter<W>],
+    ) -> Result<Status<W>, Fault<W>> {
+        let isa = self.isa.clone().ok_or(Fault::UnknownOperation)?;
+        let operation = isa.custom(opcode).ok_or(Fault::UnknownOperation)?;
+
+        let values = parameters
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| target != Some(*index))
+            .map(|(_, parameter)| self.get_input(parameter))
+            .collect::<Result<Vec<W>, _>>()?;
+
+        match operation.apply(&values) {
+            Action::Continue => {}
+            Action::Write(value) => {
+                let index = target.ok_or(Fault::MissingTarget)?;
+                let address = self.get_target(&parameters[index])?;
+                self.write_at(address, value);
+            }
+            Action::Jump(position) => self.jump_to(position)?,
+            Action::Output(_) if !self.may_output() => return Err(Fault::OutputLimit),
+            Action::Output(value) => return Ok(Status::Output(value)),
+            Action::Halt => {
+                self.jump(start);
+                return Ok(Status::Halted);
+            }
+        }
+
+        Ok(Status::Running)
+    }
+
+    /// Executes instructions until the program produces an output, waits for
+    /// an input or halts.
+    pub fn resume(&mut self) -> Result<Status<W>, IntcodeError<W>> {
+        self.resume_traced(&mut ())
+    }
+
+    /// Executes instructions like `resume` and reports each one to `tracer`.
+    pub fn resume_traced<T>(&mut self, tracer: &mut T) -> Result<Status<W>, IntcodeError<W>>
+    where
+        T: Tracer<W> + ?Sized,
+    {
+        let (_, status) = self.resume_at(tracer)?;
+        Ok(status)
+    }
+
+    /// Executes instructions until one of them does not return `Running`,
+    /// and returns its position along with its status.
+    fn resume_at<T>(&mut self, tracer: &mut T) -> Result<(usize, Status<W>), IntcodeError<W>>
+    where
+        T: Tracer<W> + ?Sized,
+    {
+        if !tracer.is_enabled() && self.budget.is_none() && self.cycles.is_none() {
+            return self.resume_untraced();
+        }
+
+        loop {
+            let start = self.position;
+            match self.step_traced(tracer)? {
+                Status::Running => continue,
+                status => return Ok((start, status)),
+            }
+        }
+    }
+
+    /// Executes instructions like `resume_at`. Without tracing, limits or
+    /// cycle detection, nothing needs to happen between two instructions, so
+    /// the decode cache is taken out of the machine just once.
+    fn resume_untraced(&mut self) -> Result<(usize, Status<W>), IntcodeError<W>> {
+        let mut cache = self.cache.take();
+        let result = loop {
+            let start = self.position;
+            let status = match &mut cache {
+                Some(cache) => self.execute_cached(cache, start, &mut ()),
+                None => instruction::decode(&self.memory, start, self.isa.as_deref())
+                    .and_then(|decoded| self.execute_decoded(start, &decoded, &mut ())),
+            };
+
+            match status {
+                Ok(Status::Running) => {}
+                Ok(status) => break Ok((start, status)),
+                Err(fault) => {
+                    self.jump(start);
+                    break Err(self.error(start, fault));
                 }
-                Operation::JumpIfTrue => {
-                    assert_eq!(parameters.len(), 2);
+            }
+        };
+
+        self.cache = cache;
+        result
+    }
+
+    /// Executes instructions until the program waits for an input or halts,
+    /// collecting its outputs in a buffer. Returns `NeedsInput` or `Halted`.
+    pub fn pump(&mut self) -> Result<Status<W>, IntcodeError<W>> {
+        loop {
+            match self.resume()? {
+                Status::Output(value) => self.outputs.push_back(value),
+                status => return Ok(status),
+            }
+        }
+    }
 
-                    let condition = self.get_input(&parameters[0]);
-                    let jump_position = self.get_input(&parameters[1]);
+    /// Removes and returns the outputs collected by `pump`.
+    pub fn take_outputs(&mut self) -> Vec<W> {
+        self.outputs.drain(..).collect()
+    }
 
-                    if condition != 0 {
-                        self.jump(jump_position as usize);
-                    }
+    /// Runs the program to completion on the given inputs and collects its
+    /// outputs. Running out of inputs is an error. If the machine stops at a
+    /// limit, the outputs it emitted so far are kept for `take_outputs`.
+    pub fn run(&mut self, inputs: Vec<W>) -> Result<Vec<W>, IntcodeError<W>> {
+        let mut outputs = vec![];
+        match self.run_with(&mut IterInput(inputs.into_iter()), &mut outputs) {
+            Ok(()) => Ok(outputs),
+            Err(error) => {
+                if error.is_limit() {
+                    self.outputs.extend(outputs);
                 }
-                Operation::JumpIfFalse => {
-                    assert_eq!(parameters.len(), 2);
+                Err(error)
+            }
+        }
+    }
 
-                    let condition = self.get_input(&parameters[0]);
-                    let jump_position = self.get_input(&parameters[1]);
+    /// Undoes an output instruction at `start` whose value was not accepted,
+    /// so resuming emits it again.
+    fn rewind_output(&mut self, start: usize) {
+        self.jump(start);
+        self.forget_cycles();
+        if let Some(budget) = &mut self.budget {
+            budget.executed -= 1;
+            budget.outputs -= 1;
+        }
+    }
 
-                    if condition == 0 {
-                        self.jump(jump_position as usize);
+    /// Runs the program to completion, reading inputs from `input` whenever
+    /// the internal input queue is empty and writing all outputs to `output`.
+    pub fn run_with<I, O>(&mut self, input: &mut I, output: &mut O) -> Result<(), IntcodeError<W>>
+    where
+        I: InputSource<W> + ?Sized,
+        O: OutputSink<W> + ?Sized,
+    {
+        loop {
+            match self.resume_at(&mut ())? {
+                (_, Status::Running) => continue,
+                (start, Status::Output(value)) => {
+                    if output.write_output(value).is_err() {
+                        self.rewind_output(start);
+                        return Err(self.error(start, Fault::OutputClosed));
                     }
                 }
-                Operation::LessThan => {
-                    assert_eq!(parameters.len(), 3);
+                (start, Status::NeedsInput) => match input.next_input() {
+                    Some(value) => self.provide_input(value),
+                    None => return Err(self.error(start, Fault::MissingInput)),
+                },
+                (_, Status::Halted) => return Ok(()),
+            }
+        }
+    }
+}
 
-                    let a = self.get_input(&parameters[0]);
-                    let b = self.get_input(&parameters[1]);
-                    let result = if a < b { 1 } else { 0 };
-                    self.write_at(self.get_target(&parameters[2]), result);
-                }
-                Operation::Equals => {
-                    assert_eq!(parameters.len(), 3);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                    let a = self.get_input(&parameters[0]);
-                    let b = self.get_input(&parameters[1]);
-                    let result = if a == b { 1 } else { 0 };
-                    self.write_at(self.get_target(&parameters[2]), result);
-                }
-                Operation::Halt => break,
+    #[test]
+    fn run_equal_to_8() {
+        let program = Intcode::new(vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8]);
+        assert_eq!(program.clone().run(vec![8]).unwrap(), vec![1]);
+        assert_eq!(program.clone().run(vec![7]).unwrap(), vec![0]);
+    }
+
+    #[test]
+    fn pause_on_missing_input() {
+        let mut program = Intcode::new(vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8]);
+
+        assert_eq!(program.resume().unwrap(), Status::NeedsInput);
+        assert_eq!(program.resume().unwrap(), Status::NeedsInput);
+
+        program.provide_input(8);
+        assert_eq!(program.resume().unwrap(), Status::Output(1));
+        assert_eq!(program.resume().unwrap(), Status::Halted);
+        assert_eq!(program.step().unwrap(), Status::Halted);
+    }
+
+    #[test]
+    fn step_single_instructions() {
+        let mut program = Intcode::new(vec![1101, 2, 3, 5, 4, 0, 99]);
+
+        assert_eq!(program.step().unwrap(), Status::Running);
+        assert_eq!(program.step().unwrap(), Status::Output(5));
+        assert_eq!(program.step().unwrap(), Status::Halted);
+    }
+
+    #[test]
+    fn feed_inputs_incrementally() {
+        // Echoes inputs until it reads a zero.
+        let mut program = Intcode::new(vec![3, 11, 4, 11, 1005, 11, 0, 99, 0, 0, 0, 0]);
+        let mut outputs = vec![];
+
+        for input in [3, 2, 1, 0].iter() {
+            assert_eq!(program.resume().unwrap(), Status::NeedsInput);
+            program.provide_input(*input);
+            if let Status::Output(value) = program.resume().unwrap() {
+                outputs.push(value);
             }
         }
 
-        outputs
+        assert_eq!(outputs, vec![3, 2, 1, 0]);
+        assert_eq!(program.resume().unwrap(), Status::Halted);
     }
 
-    fn next(&mut self) -> (Operation, Vec<Parameter>) {
-        let parameter_code = self.read_one();
-        let mut remainder = parameter_code / 100;
-        let opcode = parameter_code % 100;
-        let mut parameters: Vec<Parameter> = Vec::new();
+    #[test]
+    fn relative_mode_read() {
+        let mut program = Intcode::new(vec![109, 5, 204, 2, 99, 0, 0, 42]);
+        assert_eq!(program.run(vec![]).unwrap(), vec![42]);
+    }
 
-        let (operation, size) = match opcode {
-            1 => (Operation::Add, 3),
-            2 => (Operation::Multiply, 3),
-            3 => (Operation::Input, 1),
-            4 => (Operation::Output, 1),
-            5 => (Operation::JumpIfTrue, 2),
-            6 => (Operation::JumpIfFalse, 2),
-            7 => (Operation::LessThan, 3),
-            8 => (Operation::Equals, 3),
-            99 => (Operation::Halt, 0),
-            _ => panic!("Unknown operation {}", opcode),
-        };
+    #[test]
+    fn relative_mode_write() {
+        // Moves the base backwards and stores the input through it.
+        let mut program = Intcode::new(vec![109, 14, 109, -4, 203, 0, 4, 10, 99, 0, 0]);
+        assert_eq!(program.run(vec![7]).unwrap(), vec![7]);
+        assert_eq!(program.relative_base, 10);
+    }
 
-        for _ in 0..size {
-            let mode = match remainder % 10 {
-                0 => Parameter::Position,
-                1 => Parameter::Immediate,
-                _ => panic!("Unknown mode {}", remainder % 10),
-            };
-            remainder = remainder / 10;
+    #[test]
+    fn memory_beyond_program() {
+        // Copies an input to a scratch cell far behind the program and back.
+        let mut program = Intcode::new(vec![3, 1000, 1001, 1000, 1, 5000000000, 4, 5000000000, 99]);
+        assert_eq!(program.run(vec![41]).unwrap(), vec![42]);
+        assert_eq!(program.memory.get(1000), 41);
+        assert_eq!(program.memory.get(1001), 0);
+    }
+
+    #[test]
+    fn quine() {
+        let data = vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        let mut program = Intcode::new(data.clone());
+        assert_eq!(program.run(vec![]).unwrap(), data);
+    }
+
+    #[test]
+    fn decode_cache_sees_self_modification() {
+        // Increments the operand of its own output instruction.
+        let mut program =
+            Intcode::new(vec![104, 1, 1001, 1, 1, 1, 1007, 1, 3, 20, 1005, 20, 0, 99]);
+        program.enable_decode_cache();
+        assert_eq!(program.run(vec![]).unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn decode_cache_runs_diagnostics() -> Result<(), IntcodeError> {
+        let program = Intcode::from_file("inputs/day05.txt")?;
+        let mut cached = program.clone();
+        cached.enable_decode_cache();
+
+        assert_eq!(cached.run(vec![5])?, program.clone().run(vec![5])?);
+        Ok(())
+    }
+
+    #[test]
+    fn large_multiplication() {
+        let mut program = Intcode::new(vec![1102, 34915192, 34915192, 7, 4, 7, 99, 0]);
+        assert_eq!(program.run(vec![]).unwrap(), vec![1219070632396864]);
+    }
+
+    #[test]
+    fn overflow_is_an_error() {
+        let mut program = Intcode::<i32>::with_words(vec![1102, 65536, 65536, 5, 99, 0]);
+        assert!(matches!(
+            program.step(),
+            Err(IntcodeError::Overflow {
+                position: 0,
+                opcode: 1102
+            })
+        ));
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn bigint_words() {
+        use num_bigint::BigInt;
+
+        let data = "1102,9223372036854775807,4,7,4,7,99,0"
+            .split(',')
+            .map(|word| word.parse().unwrap())
+            .collect();
+        let mut program = Intcode::<BigInt>::with_words(data);
+        assert_eq!(
+            program.run(vec![]).unwrap(),
+            vec!["36893488147419103228".parse::<BigInt>().unwrap()]
+        );
+    }
+
+    #[test]
+    fn end_of_program() {
+        let mut program = Intcode::new(vec![1, 0, 0, 0]);
+        let error = program.run(vec![]).unwrap_err();
+
+        assert!(matches!(
+            error,
+            IntcodeError::EndOfProgram {
+                position: 4,
+                opcode: 0
+            }
+        ));
+        assert_eq!(error.opcode(), Some(&0));
+
+        // Writing past the program does not move its end.
+        let mut program = Intcode::new(vec![1101, 0, 0, 6]);
+        assert!(matches!(
+            program.run(vec![]),
+            Err(IntcodeError::EndOfProgram { position: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn run_patched() {
+        let program = Intcode::new(vec![1, 0, 0, 0, 99]);
+
+        assert_eq!(program.run_patched(&[]).unwrap(), 2);
+        assert_eq!(program.run_patched(&[(1, 4), (2, 4)]).unwrap(), 198);
+        assert_eq!(program.memory().get(1), 0);
+    }
 
-            let parameter = mode(self.read_one());
-            parameters.push(parameter);
+    #[test]
+    fn instruction_limit() {
+        // Counts up forever.
+        let mut program = Intcode::new(vec![1001, 7, 1, 7, 1105, 1, 0, 0]);
+        program.set_limits(Limits {
+            instructions: Some(10),
+            ..Limits::default()
+        });
+
+        let error = program.run(vec![]).unwrap_err();
+        assert!(matches!(
+            error,
+            IntcodeError::InstructionLimit {
+                position: 0,
+                opcode: 1001,
+                executed: 10
+            }
+        ));
+        assert!(error.is_limit());
+        assert_eq!(program.memory().get(7), 5);
+
+        program.set_limits(Limits {
+            instructions: Some(3),
+            ..Limits::default()
+        });
+        assert!(matches!(
+            program.run(vec![]),
+            Err(IntcodeError::InstructionLimit {
+                position: 4,
+                executed: 3,
+                ..
+            })
+        ));
+        assert_eq!(program.memory().get(7), 7);
+    }
+
+    #[test]
+    fn timeout() {
+        let mut program = Intcode::new(vec![1105, 1, 0]);
+        let time = std::time::Duration::from_millis(10);
+        program.set_limits(Limits {
+            time: Some(time),
+            ..Limits::default()
+        });
+
+        match program.run(vec![]) {
+            Err(IntcodeError::Timeout {
+                position: 0,
+                elapsed,
+                ..
+            }) => assert!(elapsed >= time),
+            _ => panic!("Expected a timeout"),
         }
+    }
+
+    #[test]
+    fn memory_limit() {
+        let mut program = Intcode::new(vec![4, 5000, 1101, 1, 2, 1000, 99]);
+        let limits = Limits {
+            memory: Some(1000),
+            ..Limits::default()
+        };
+        program.set_limits(limits.clone());
+
+        let error = program.run(vec![]).unwrap_err();
+        assert!(matches!(
+            error,
+            IntcodeError::MemoryLimit {
+                position: 2,
+                address: 1000,
+                ..
+            }
+        ));
+        assert_eq!(program.memory().len(), 7);
+        assert_eq!(program.take_outputs(), vec![0]);
+
+        program.set_limits(Limits {
+            memory: Some(1001),
+            ..limits
+        });
+        assert_eq!(program.run(vec![]).unwrap(), vec![]);
+        assert_eq!(program.memory().get(1000), 3);
+    }
+
+    #[test]
+    fn output_limit() {
+        let mut program = Intcode::new(vec![104, 1, 1105, 1, 0]);
+        program.set_limits(Limits {
+            outputs: Some(2),
+            ..Limits::default()
+        });
+
+        assert_eq!(program.resume().unwrap(), Status::Output(1));
+        assert_eq!(program.resume().unwrap(), Status::Output(1));
+        assert!(matches!(
+            program.resume(),
+            Err(IntcodeError::OutputLimit {
+                position: 0,
+                outputs: 2,
+                ..
+            })
+        ));
+        assert_eq!(program.limits().and_then(|limits| limits.outputs), Some(2));
+    }
+
+    #[test]
+    fn unknown_operation() {
+        let mut program = Intcode::new(vec![1101, 1, 1, 0, 42]);
+        let error = program.run(vec![]).unwrap_err();
+
+        assert!(matches!(
+            error,
+            IntcodeError::UnknownOperation {
+                position: 4,
+                opcode: 42
+            }
+        ));
+        assert_eq!(program.position, 4);
+    }
+
+    #[test]
+    fn unknown_mode() {
+        let mut program = Intcode::new(vec![301, 0, 0, 0, 99]);
+        assert!(matches!(
+            program.step(),
+            Err(IntcodeError::UnknownMode {
+                position: 0,
+                opcode: 301,
+                mode: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn immediate_target() {
+        let mut program = Intcode::new(vec![11101, 1, 1, 0, 99]);
+        assert!(matches!(
+            program.step(),
+            Err(IntcodeError::ImmediateTarget {
+                position: 0,
+                opcode: 11101
+            })
+        ));
+    }
+
+    #[test]
+    fn missing_input() {
+        let mut program = Intcode::new(vec![3, 0, 3, 0, 99]);
+        assert!(matches!(
+            program.run(vec![1]),
+            Err(IntcodeError::MissingInput {
+                position: 2,
+                opcode: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn invalid_address() {
+        let mut program = Intcode::new(vec![4, -1, 99]);
+        let error = program.step().unwrap_err();
+
+        assert_eq!(error.position(), Some(0));
+        assert_eq!(error.opcode(), Some(&4));
+        assert!(matches!(
+            error,
+            IntcodeError::InvalidAddress { address: -1, .. }
+        ));
+    }
 
-        (operation, parameters)
+    #[test]
+    fn missing_file() {
+        assert!(matches!(
+            Intcode::from_file("inputs/missing.txt"),
+            Err(IntcodeError::Io(_))
+        ));
     }
 }
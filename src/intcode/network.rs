@@ -0,0 +1,471 @@
+use super::{Intcode, IntcodeError, Status};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+
+/// A packet sent from one machine of a network to another.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Packet {
+    pub source: usize,
+    pub destination: i64,
+    pub payload: Vec<i64>,
+}
+
+pub struct NetworkConfig {
+    /// The number of words a machine outputs per packet, including the
+    /// destination address.
+    pub packet_size: usize,
+    /// The input a machine receives when it reads from an empty inbox. If
+    /// `None`, the machine blocks until a packet arrives.
+    pub idle_input: Option<i64>,
+    /// The number of consecutive reads from an empty inbox after which a
+    /// machine counts as idle.
+    pub idle_threshold: usize,
+    /// Whether every machine reads its own address as its first input.
+    pub boot_with_address: bool,
+    /// The maximum number of instructions a machine executes before the next
+    /// machine gets its turn.
+    pub time_slice: usize,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            packet_size: 3,
+            idle_input: Some(-1),
+            idle_threshold: 2,
+            boot_with_address: true,
+            time_slice: 1000,
+        }
+    }
+}
+
+/// Tells the network whether to keep running after a monitor was called.
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    Continue,
+    Stop,
+}
+
+/// Why a network stopped running.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// The monitor asked the network to stop.
+    Stopped,
+    /// Every machine halted.
+    Halted,
+    /// Every machine is blocked on an empty inbox and the monitor did not send
+    /// any packet.
+    Deadlocked,
+    /// Every machine is idle or halted, and the monitor did not send any
+    /// packet. The network would only keep feeding idle inputs.
+    Idle,
+}
+
+/// Watches the whole network, e.g. to act as a NAT.
+pub trait Monitor {
+    /// Called for every packet a machine sends. Packets to addresses outside
+    /// of the network are only seen by the monitor and then dropped.
+    fn on_packet(&mut self, _packet: &Packet, _network: &mut Network) -> Action {
+        Action::Continue
+    }
+
+    /// Called whenever no packet was sent in a full round and every machine is
+    /// idle or halted.
+    fn on_idle(&mut self, _network: &mut Network) -> Action {
+        Action::Continue
+    }
+}
+
+impl Monitor for () {}
+
+#[derive(Debug)]
+pub enum NetworkError {
+    /// A machine failed while the network was running.
+    Machine { address: usize, error: IntcodeError },
+    /// Packets must at least hold their destination address.
+    EmptyPackets,
+    /// Machines must execute at least one instruction per turn.
+    EmptyTimeSlice,
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::Machine { address, error } => {
+                write!(f, "Machine {} failed: {}", address, error)
+            }
+            NetworkError::EmptyPackets => write!(f, "Packets need at least one word"),
+            NetworkError::EmptyTimeSlice => write!(f, "Time slices need at least one instruction"),
+        }
+    }
+}
+
+impl Error for NetworkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            NetworkError::Machine { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+struct Node {
+    machine: Intcode,
+    inbox: VecDeque<i64>,
+    outbox: Vec<i64>,
+    idle_reads: usize,
+    blocked: bool,
+    halted: bool,
+}
+
+impl Node {
+    fn is_idle(&self, config: &NetworkConfig) -> bool {
+        self.halted
+            || self.blocked
+            || (self.inbox.is_empty() && self.idle_reads >= config.idle_threshold)
+    }
+}
+
+/// Runs many Intcode machines side by side and routes packets between them.
+///
+/// Machines are scheduled cooperatively: each machine runs until it waits for
+/// a packet, halts or used up its time slice, then the next one gets its turn.
+/// Whenever a machine has written a full packet, its payload is appended to
+/// the inbox of the machine at the destination address.
+pub struct Network {
+    nodes: Vec<Node>,
+    config: NetworkConfig,
+}
+
+impl Network {
+    /// Creates a network of `size` copies of `program`, or fails if the
+    /// configuration cannot work.
+    pub fn new(
+        program: &Intcode,
+        size: usize,
+        config: NetworkConfig,
+    ) -> Result<Network, NetworkError> {
+        if config.packet_size == 0 {
+            return Err(NetworkError::EmptyPackets);
+        }
+        if config.time_slice == 0 {
+            return Err(NetworkError::EmptyTimeSlice);
+        }
+
+        let nodes = (0..size)
+            .map(|address| {
+                let mut machine = program.clone();
+                if config.boot_with_address {
+                    machine.provide_input(address as i64);
+                }
+
+                Node {
+                    machine,
+                    inbox: VecDeque::new(),
+                    outbox: vec![],
+                    idle_reads: 0,
+                    blocked: false,
+                    halted: false,
+                }
+            })
+            .collect();
+
+        Ok(Network { nodes, config })
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn machine(&self, address: usize) -> &Intcode {
+        &self.nodes[address].machine
+    }
+
+    /// Appends a payload to the inbox of a machine. Returns `false` if there is
+    /// no machine with this address.
+    pub fn send(&mut self, destination: i64, payload: &[i64]) -> bool {
+        let node = match self.node_index(destination) {
+            Some(index) => &mut self.nodes[index],
+            None => return false,
+        };
+
+        node.inbox.extend(payload);
+        node.blocked = false;
+        true
+    }
+
+    /// Whether every machine is idle or halted and no packet is waiting to be
+    /// read.
+    pub fn is_idle(&self) -> bool {
+        self.nodes.iter().all(|node| node.is_idle(&self.config))
+    }
+
+    fn node_index(&self, address: i64) -> Option<usize> {
+        if address >= 0 && (address as usize) < self.nodes.len() {
+            Some(address as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Runs the network until the monitor stops it, every machine halted, or
+    /// the network deadlocked or went idle.
+    pub fn run<M: Monitor + ?Sized>(&mut self, monitor: &mut M) -> Result<Outcome, NetworkError> {
+        loop {
+            let mut sent = false;
+
+            for address in 0..self.nodes.len() {
+                let packets = self.run_node(address)?;
+                sent = sent || !packets.is_empty();
+
+                for packet in packets {
+                    if monitor.on_packet(&packet, self) == Action::Stop {
+                        return Ok(Outcome::Stopped);
+                    }
+                    self.send(packet.destination, &packet.payload);
+                }
+            }
+
+            if self.nodes.iter().all(|node| node.halted) {
+                return Ok(Outcome::Halted);
+            }
+
+            if !sent && self.is_idle() {
+                if monitor.on_idle(self) == Action::Stop {
+                    return Ok(Outcome::Stopped);
+                }
+
+                let blocked = self.nodes.iter().all(|node| node.halted || node.blocked);
+                if blocked {
+                    return Ok(Outcome::Deadlocked);
+                }
+                if self.is_idle() {
+                    return Ok(Outcome::Idle);
+                }
+            }
+        }
+    }
+
+    /// Runs a single machine for one time slice and returns the packets it
+    /// sent.
+    fn run_node(&mut self, address: usize) -> Result<Vec<Packet>, NetworkError> {
+        let config = &self.config;
+        let node = &mut self.nodes[address];
+        let mut packets = vec![];
+
+        if node.halted || node.blocked {
+            return Ok(packets);
+        }
+
+        for _ in 0..config.time_slice {
+            let status = node
+                .machine
+                .step()
+                .map_err(|error| NetworkError::Machine { address, error })?;
+
+            match status {
+                Status::Running => {}
+                Status::Output(value) => {
+                    node.outbox.push(value);
+
+                    if node.outbox.len() == config.packet_size {
+                        if let Some((destination, payload)) = node.outbox.split_first() {
+                            packets.push(Packet {
+                                source: address,
+                                destination: *destination,
+                                payload: payload.to_vec(),
+                            });
+                        }
+                        node.outbox.clear();
+                    }
+                }
+                Status::NeedsInput => {
+                    if let Some(value) = node.inbox.pop_front() {
+                        node.idle_reads = 0;
+                        node.machine.provide_input(value);
+                    } else if let Some(value) = config.idle_input {
+                        node.idle_reads += 1;
+                        node.machine.provide_input(value);
+                        break;
+                    } else {
+                        node.blocked = true;
+                        break;
+                    }
+                }
+                Status::Halted => {
+                    node.halted = true;
+                    break;
+                }
+            }
+        }
+
+        Ok(packets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Boots with its address `a`, then forwards every packet `(x, y)` it
+    /// receives as `(x, y + 1)` to address `a + 1`.
+    fn forwarder() -> Intcode {
+        Intcode::new(vec![
+            3, 100, 1001, 100, 1, 101, 3, 102, 1008, 102, -1, 103, 1005, 103, 6, 3, 104, 1001, 104,
+            1, 104, 4, 101, 4, 102, 4, 104, 1105, 1, 6,
+        ])
+    }
+
+    struct Nat {
+        last: Option<Packet>,
+        restarts: usize,
+    }
+
+    impl Monitor for Nat {
+        fn on_packet(&mut self, packet: &Packet, network: &mut Network) -> Action {
+            if packet.destination as usize >= network.len() {
+                self.last = Some(packet.clone());
+            }
+            Action::Continue
+        }
+
+        fn on_idle(&mut self, network: &mut Network) -> Action {
+            let packet = match self.last.take() {
+                Some(packet) => packet,
+                None => return Action::Stop,
+            };
+            if packet.payload[1] >= 9 {
+                self.last = Some(packet);
+                return Action::Stop;
+            }
+
+            self.restarts += 1;
+            network.send(0, &packet.payload);
+            Action::Continue
+        }
+    }
+
+    #[test]
+    fn route_packets() {
+        struct Capture(Vec<Packet>);
+
+        impl Monitor for Capture {
+            fn on_packet(&mut self, packet: &Packet, _network: &mut Network) -> Action {
+                self.0.push(packet.clone());
+                if packet.destination == 3 {
+                    Action::Stop
+                } else {
+                    Action::Continue
+                }
+            }
+        }
+
+        let mut network = Network::new(&forwarder(), 3, NetworkConfig::default()).unwrap();
+        network.send(0, &[7, 0]);
+
+        let mut capture = Capture(vec![]);
+        assert_eq!(network.run(&mut capture).unwrap(), Outcome::Stopped);
+        assert_eq!(
+            capture.0,
+            vec![
+                Packet {
+                    source: 0,
+                    destination: 1,
+                    payload: vec![7, 1]
+                },
+                Packet {
+                    source: 1,
+                    destination: 2,
+                    payload: vec![7, 2]
+                },
+                Packet {
+                    source: 2,
+                    destination: 3,
+                    payload: vec![7, 3]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn nat_restarts_idle_network() {
+        let mut network = Network::new(&forwarder(), 3, NetworkConfig::default()).unwrap();
+        network.send(0, &[7, 0]);
+
+        let mut nat = Nat {
+            last: None,
+            restarts: 0,
+        };
+        assert_eq!(network.run(&mut nat).unwrap(), Outcome::Stopped);
+        assert_eq!(nat.restarts, 2);
+        assert_eq!(nat.last.unwrap().payload, vec![7, 9]);
+    }
+
+    #[test]
+    fn deadlock_without_idle_input() {
+        let config = NetworkConfig {
+            idle_input: None,
+            ..NetworkConfig::default()
+        };
+        let mut network = Network::new(&forwarder(), 2, config).unwrap();
+
+        assert_eq!(network.run(&mut ()).unwrap(), Outcome::Deadlocked);
+        assert!(network.is_idle());
+    }
+
+    #[test]
+    fn halted_network() {
+        let program = Intcode::new(vec![3, 10, 104, 1, 104, 2, 104, 3, 99]);
+        let mut network = Network::new(&program, 4, NetworkConfig::default()).unwrap();
+
+        assert_eq!(network.run(&mut ()).unwrap(), Outcome::Halted);
+    }
+
+    #[test]
+    fn machine_errors() {
+        let program = Intcode::new(vec![3, 10, 42]);
+        let mut network = Network::new(&program, 2, NetworkConfig::default()).unwrap();
+        let error = network.run(&mut ()).unwrap_err();
+
+        assert!(matches!(
+            error,
+            NetworkError::Machine {
+                address: 0,
+                error: IntcodeError::UnknownOperation { position: 2, .. }
+            }
+        ));
+    }
+
+    #[test]
+    fn idle_network() {
+        let mut network = Network::new(&forwarder(), 3, NetworkConfig::default()).unwrap();
+        network.send(0, &[7, 0]);
+
+        assert_eq!(network.run(&mut ()).unwrap(), Outcome::Idle);
+        assert!(network.is_idle());
+    }
+
+    #[test]
+    fn invalid_configs() {
+        let config = |packet_size, time_slice| NetworkConfig {
+            packet_size,
+            time_slice,
+            ..NetworkConfig::default()
+        };
+
+        assert!(matches!(
+            Network::new(&forwarder(), 2, config(0, 1000)),
+            Err(NetworkError::EmptyPackets)
+        ));
+        assert!(matches!(
+            Network::new(&forwarder(), 2, config(3, 0)),
+            Err(NetworkError::EmptyTimeSlice)
+        ));
+        assert!(Network::new(&forwarder(), 2, config(1, 1)).is_ok());
+    }
+}
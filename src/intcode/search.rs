@@ -0,0 +1,453 @@
+use super::compile::Compiled;
+use super::{Intcode, IntcodeError};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// The number of candidates a thread of a brute force search claims at once.
+const CHUNK_SIZE: usize = 64;
+
+/// Searches for the values of some memory addresses that make a program leave
+/// a target value at another address once it halts.
+///
+/// Programs like the gravity assist program of day 2 take their parameters
+/// this way, patched into memory before they run.
+#[derive(Clone, Debug)]
+pub struct Search {
+    /// The addresses to patch, each with the values to try.
+    pub inputs: Vec<(usize, Range<i64>)>,
+    /// The address the result is read from.
+    pub output: usize,
+    pub target: i64,
+    /// The number of threads a brute force search runs on.
+    pub threads: usize,
+}
+
+#[derive(Debug)]
+pub enum SearchError {
+    Machine(IntcodeError),
+    /// The result does not depend linearly on the inputs, so `solve_linear`
+    /// cannot be used.
+    NonLinear,
+    /// There are more combinations of input values than fit into a `usize`.
+    TooManyCandidates,
+}
+
+impl Search {
+    /// Creates a search that runs on as many threads as the machine has
+    /// cores.
+    pub fn new(inputs: Vec<(usize, Range<i64>)>, output: usize, target: i64) -> Search {
+        let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+
+        Search {
+            inputs,
+            output,
+            target,
+            threads,
+        }
+    }
+
+    /// Runs the program on the given input values and returns the result.
+    pub fn evaluate(&self, program: &Intcode, values: &[i64]) -> Result<i64, IntcodeError> {
+        let mut machine = program.clone();
+        machine.restart_limits();
+        for ((address, _), value) in self.inputs.iter().zip(values) {
+            machine.patch(*address, *value);
+        }
+
+        machine.run(vec![])?;
+        Ok(machine.memory().get(self.output))
+    }
+
+    /// Like `evaluate`, on a compiled program, which the searches run many
+    /// times.
+    fn evaluate_compiled(&self, program: &Compiled, values: &[i64]) -> Result<i64, IntcodeError> {
+        let mut machine = program.machine();
+        for ((address, _), value) in self.inputs.iter().zip(values) {
+            machine.patch(*address, *value);
+        }
+
+        machine.run(vec![])?;
+        Ok(machine.get(self.output))
+    }
+
+    /// Solves the search as cheaply as possible: with `solve_linear` if the
+    /// result is linear in the inputs, and with `brute_force` otherwise. A
+    /// program that fails while its linearity is checked is searched by brute
+    /// force as well, which reports the error if no earlier candidate solves
+    /// the search.
+    pub fn solve(&self, program: &Intcode) -> Result<Option<Vec<i64>>, SearchError> {
+        match self.solve_linear(program) {
+            Ok(solution) => Ok(solution),
+            Err(SearchError::NonLinear) | Err(SearchError::Machine(_)) => self.brute_force(program),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Tries every combination of input values, spread over `threads`
+    /// threads.
+    ///
+    /// Returns the first solution in the order of nested loops over the
+    /// inputs, with the last input in the innermost loop, just like a
+    /// sequential search would. If the program fails before a solution is
+    /// found, the error is returned instead.
+    pub fn brute_force(&self, program: &Intcode) -> Result<Option<Vec<i64>>, SearchError> {
+        let lens = self.range_lens()?;
+        let total = combinations(&lens)?;
+        let program = Compiled::new(program);
+        let next_chunk = AtomicUsize::new(0);
+        // The index of the earliest solution or error found so far.
+        let found = AtomicUsize::new(usize::MAX);
+        let error: Mutex<Option<(usize, IntcodeError)>> = Mutex::new(None);
+
+        thread::scope(|scope| {
+            for _ in 0..self.threads.max(1) {
+                scope.spawn(|| loop {
+                    let start = next_chunk.fetch_add(1, Ordering::Relaxed) * CHUNK_SIZE;
+                    if start >= total || start >= found.load(Ordering::Relaxed) {
+                        return;
+                    }
+
+                    for index in start..(start + CHUNK_SIZE).min(total) {
+                        if index >= found.load(Ordering::Relaxed) {
+                            return;
+                        }
+
+                        match self.evaluate_compiled(&program, &self.values(&lens, index)) {
+                            Ok(result) if result == self.target => {
+                                found.fetch_min(index, Ordering::Relaxed);
+                                return;
+                            }
+                            Ok(_) => {}
+                            Err(failure) => {
+                                found.fetch_min(index, Ordering::Relaxed);
+                                let mut error = error.lock().unwrap();
+                                if error.as_ref().is_none_or(|(first, _)| index < *first) {
+                                    *error = Some((index, failure));
+                                }
+                                return;
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        let found = found.into_inner();
+        match error.into_inner().unwrap() {
+            Some((index, error)) if index == found => Err(SearchError::Machine(error)),
+            _ if found == usize::MAX => Ok(None),
+            _ => Ok(Some(self.values(&lens, found))),
+        }
+    }
+
+    /// Solves the search analytically, assuming that the result is a linear
+    /// function of the inputs.
+    ///
+    /// The function is measured by running the program a few times, and
+    /// checked at the corners of the input ranges. The equation is then
+    /// solved for the last input that affects the result, trying every value
+    /// of the other ones. A solution is always confirmed by running the
+    /// program on it, so a program that only looks linear cannot produce a
+    /// wrong answer. Since linearity is only checked at a few points, the
+    /// model having no solution does not mean the program has none, so that
+    /// is reported as `NonLinear` as well.
+    pub fn solve_linear(&self, program: &Intcode) -> Result<Option<Vec<i64>>, SearchError> {
+        let lens = self.range_lens()?;
+        if lens.contains(&0) {
+            return Ok(None);
+        }
+
+        let program = &Compiled::new(program);
+        let base: Vec<i64> = self.inputs.iter().map(|(_, range)| range.start).collect();
+        let constant = i128::from(self.evaluate_compiled(program, &base)?);
+
+        let mut coefficients: Vec<i128> = vec![0; base.len()];
+        for (index, len) in lens.iter().enumerate() {
+            if *len > 1 {
+                let mut values = base.clone();
+                values[index] += 1;
+                coefficients[index] =
+                    i128::from(self.evaluate_compiled(program, &values)?) - constant;
+            }
+        }
+
+        let model = |values: &[i64]| -> i128 {
+            values
+                .iter()
+                .zip(&base)
+                .zip(&coefficients)
+                .map(|((value, start), coefficient)| coefficient * i128::from(value - start))
+                .sum::<i128>()
+                + constant
+        };
+
+        let last: Vec<i64> = self.inputs.iter().map(|(_, range)| range.end - 1).collect();
+        let mut probes = vec![last.clone()];
+        for index in 0..base.len() {
+            let mut values = base.clone();
+            values[index] = last[index];
+            probes.push(values);
+        }
+        for probe in probes {
+            if i128::from(self.evaluate_compiled(program, &probe)?) != model(&probe) {
+                return Err(SearchError::NonLinear);
+            }
+        }
+
+        let solved = match coefficients
+            .iter()
+            .rposition(|coefficient| *coefficient != 0)
+        {
+            Some(solved) => solved,
+            None if constant == i128::from(self.target) => return Ok(Some(base)),
+            None => return Err(SearchError::NonLinear),
+        };
+
+        let free: Vec<usize> = (0..base.len()).filter(|index| *index != solved).collect();
+        let free_lens: Vec<usize> = free.iter().map(|index| lens[*index]).collect();
+
+        for combination in 0..combinations(&free_lens)? {
+            let mut values = base.clone();
+            let mut rest = combination;
+            for (index, len) in free.iter().zip(&free_lens).rev() {
+                values[*index] = base[*index] + (rest % len) as i64;
+                rest /= len;
+            }
+
+            let remainder = i128::from(self.target) - model(&values);
+            if remainder % coefficients[solved] != 0 {
+                continue;
+            }
+
+            let value = i128::from(base[solved]) + remainder / coefficients[solved];
+            if value < i128::from(self.inputs[solved].1.start)
+                || value >= i128::from(self.inputs[solved].1.end)
+            {
+                continue;
+            }
+
+            values[solved] = value as i64;
+            if self.evaluate_compiled(program, &values)? != self.target {
+                return Err(SearchError::NonLinear);
+            }
+            return Ok(Some(values));
+        }
+
+        Err(SearchError::NonLinear)
+    }
+
+    /// The number of values of every input.
+    fn range_lens(&self) -> Result<Vec<usize>, SearchError> {
+        self.inputs
+            .iter()
+            .map(|(_, range)| range_len(range).ok_or(SearchError::TooManyCandidates))
+            .collect()
+    }
+
+    /// The input values of the candidate at `index`, in the order of nested
+    /// loops over the inputs. `lens` are the lengths of the input ranges.
+    fn values(&self, lens: &[usize], mut index: usize) -> Vec<i64> {
+        let mut values = vec![0; self.inputs.len()];
+
+        for ((value, (_, range)), len) in values.iter_mut().zip(&self.inputs).zip(lens).rev() {
+            *value = range.start + (index % len) as i64;
+            index /= len;
+        }
+
+        values
+    }
+}
+
+/// The number of values in a range, or `None` if it does not fit into a
+/// `usize`.
+fn range_len(range: &Range<i64>) -> Option<usize> {
+    if range.is_empty() {
+        return Some(0);
+    }
+
+    range
+        .end
+        .checked_sub(range.start)
+        .and_then(|len| usize::try_from(len).ok())
+}
+
+/// The number of combinations of values of ranges with the given lengths.
+fn combinations(lens: &[usize]) -> Result<usize, SearchError> {
+    lens.iter()
+        .try_fold(1usize, |total, len| total.checked_mul(*len))
+        .ok_or(SearchError::TooManyCandidates)
+}
+
+/// Finds the noun and verb, the words at addresses 1 and 2, both between 0
+/// and 99, for which the program leaves `target` at address 0.
+pub fn find_noun_verb(program: &Intcode, target: i64) -> Result<Option<(i64, i64)>, SearchError> {
+    let search = Search::new(vec![(1, 0..100), (2, 0..100)], 0, target);
+
+    Ok(search.solve(program)?.map(|values| (values[0], values[1])))
+}
+
+impl From<IntcodeError> for SearchError {
+    fn from(error: IntcodeError) -> Self {
+        SearchError::Machine(error)
+    }
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SearchError::Machine(error) => write!(f, "{}", error),
+            SearchError::NonLinear => write!(f, "Result is not linear in the inputs"),
+            SearchError::TooManyCandidates => write!(f, "Too many combinations of inputs"),
+        }
+    }
+}
+
+impl Error for SearchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SearchError::Machine(error) => Some(error),
+            SearchError::NonLinear | SearchError::TooManyCandidates => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Limits;
+    use super::*;
+
+    /// Computes `[0] = 3 * (noun + verb)`.
+    fn linear() -> Intcode {
+        Intcode::new(vec![1101, 0, 0, 0, 1002, 0, 3, 0, 99])
+    }
+
+    /// Computes `[0] = noun * verb`.
+    fn product() -> Intcode {
+        Intcode::new(vec![1102, 0, 0, 0, 99])
+    }
+
+    #[test]
+    fn find_sum() {
+        assert!(matches!(
+            find_noun_verb(&linear(), 3 * 150),
+            Ok(Some((51, 99)))
+        ));
+        assert!(matches!(find_noun_verb(&linear(), 3 * 199), Ok(None)));
+    }
+
+    #[test]
+    fn brute_force_finds_first_solution() {
+        for threads in 1..4 {
+            let search = Search {
+                threads,
+                ..Search::new(vec![(1, 1..50), (2, 1..50)], 0, 36)
+            };
+
+            assert_eq!(search.brute_force(&product()).unwrap(), Some(vec![1, 36]));
+        }
+
+        let search = Search::new(vec![(1, 0..10), (2, 0..10)], 0, 97);
+        assert_eq!(search.brute_force(&product()).unwrap(), None);
+    }
+
+    #[test]
+    fn brute_force_errors() {
+        // Jumps to its input, which fails unless it points at the halt.
+        let program = Intcode::new(vec![1106, 0, 0, 99]);
+        let search = Search::new(vec![(2, 1..8)], 0, 1106);
+
+        let error = search.brute_force(&program).unwrap_err();
+        assert!(matches!(
+            error,
+            SearchError::Machine(IntcodeError::UnknownOperation { position: 1, .. })
+        ));
+
+        let search = Search::new(vec![(2, 3..8)], 0, 1106);
+        assert_eq!(search.brute_force(&program).unwrap(), Some(vec![3]));
+
+        // Probing for linearity fails, but the first candidate is a solution.
+        assert!(matches!(
+            search.solve_linear(&program),
+            Err(SearchError::Machine(_))
+        ));
+        assert_eq!(search.solve(&program).unwrap(), Some(vec![3]));
+
+        // Loops forever unless its input is zero, and keeps to its limits.
+        let mut program = Intcode::new(vec![1105, 0, 0, 99]);
+        program.set_limits(Limits {
+            instructions: Some(100),
+            ..Limits::default()
+        });
+        let search = Search::new(vec![(1, 1..3)], 0, 1105);
+        assert!(matches!(
+            search.solve(&program),
+            Err(SearchError::Machine(IntcodeError::InstructionLimit {
+                executed: 100,
+                ..
+            }))
+        ));
+        let search = Search::new(vec![(1, 0..3)], 0, 1105);
+        assert_eq!(search.solve(&program).unwrap(), Some(vec![0]));
+    }
+
+    #[test]
+    fn too_many_candidates() {
+        let search = Search::new(vec![(1, i64::MIN..i64::MAX)], 0, 0);
+        assert!(matches!(
+            search.brute_force(&linear()),
+            Err(SearchError::TooManyCandidates)
+        ));
+        assert!(matches!(
+            search.solve(&linear()),
+            Err(SearchError::TooManyCandidates)
+        ));
+
+        let search = Search::new(vec![(1, 0..i64::MAX), (2, 0..i64::MAX)], 0, 0);
+        assert!(matches!(
+            search.brute_force(&product()),
+            Err(SearchError::TooManyCandidates)
+        ));
+    }
+
+    #[test]
+    fn solve_linear_programs() {
+        let search = Search::new(vec![(1, 0..100), (2, 0..100)], 0, 3 * 120);
+        assert_eq!(search.solve_linear(&linear()).unwrap(), Some(vec![21, 99]));
+
+        // Without a solution in the model, only brute force can tell.
+        let search = Search::new(vec![(1, 0..100), (2, 0..100)], 0, 2);
+        assert!(matches!(
+            search.solve_linear(&linear()),
+            Err(SearchError::NonLinear)
+        ));
+        assert_eq!(search.solve(&linear()).unwrap(), None);
+
+        let search = Search::new(vec![(1, 0..100), (2, 0..100)], 0, 36);
+        assert!(matches!(
+            search.solve_linear(&product()),
+            Err(SearchError::NonLinear)
+        ));
+        assert_eq!(search.solve(&product()).unwrap(), Some(vec![1, 36]));
+    }
+
+    #[test]
+    fn linear_between_the_probes() {
+        // Leaves 0 at address 0, but 1000 if the noun is 50.
+        let program = Intcode::new(vec![
+            1101, 0, 0, 0, 1008, 1, 50, 16, 1006, 16, 15, 1101, 1000, 0, 0, 99, 0,
+        ]);
+        let search = Search::new(vec![(1, 0..100), (2, 0..100)], 0, 1000);
+
+        assert!(matches!(
+            search.solve_linear(&program),
+            Err(SearchError::NonLinear)
+        ));
+        assert_eq!(search.solve(&program).unwrap(), Some(vec![50, 0]));
+        assert!(matches!(find_noun_verb(&program, 1000), Ok(Some((50, 0)))));
+    }
+}
@@ -0,0 +1,458 @@
+//! Saving and restoring the full state of a machine.
+//!
+//! A snapshot is a text file. The first line names the format and its version,
+//! every following line holds a key and its value:
+//!
+//! ```text
+//! intcode-snapshot 2
+//! position 4
+//! relative-base 0
+//! program-length 10
+//! memory 0 3,9,4,9,99,0,0,0,0,5
+//! memory 1048576 42
+//! input 7,8
+//! output 5
+//! instruction-set day05
+//! instruction-limit 1000000 12
+//! time-limit 2 500000000
+//! memory-limit 4096
+//! output-limit 10 1
+//! ```
+//!
+//! * `position` is the instruction pointer and `relative-base` the relative
+//!   base. Both are required.
+//! * `program-length` is the number of words of the program that was loaded.
+//!   Execution past them ends the program. Snapshots without it use the
+//!   length of the memory.
+//! * Every `memory` line holds an address, followed by the words stored from
+//!   this address on. Cells that are not listed read as zero.
+//! * `input` holds the queued inputs and `output` the outputs collected by
+//!   `Intcode::pump` that were not taken yet. Both are optional.
+//! * `instruction-set` names the profile of the instruction set, see
+//!   `InstructionSet::profile`. Machines with custom operations cannot be
+//!   saved, since their semantics are code.
+//! * The `*-limit` lines hold the limits of the machine, see `Limits`. The
+//!   instruction and output limits are followed by the instructions executed
+//!   and the outputs emitted so far, the time limit is given in seconds and
+//!   nanoseconds. The clock of the time limit restarts when the snapshot is
+//!   restored.
+//!
+//! Snapshots of version 1, which predate instruction sets and limits, can
+//! still be restored.
+//!
+//! Empty lines are ignored. Words are written in the `Display` format of the
+//! word type, so a snapshot can only be restored with the word type it was
+//! saved with.
+
+use super::isa::InstructionSet;
+use super::limits::{Budget, Limits};
+use super::{Intcode, Word};
+use std::convert::{TryFrom, TryInto};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, prelude::*, BufReader, BufWriter};
+use std::time::Duration;
+
+const HEADER: &str = "intcode-snapshot";
+const VERSION: u32 = 2;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// The first line does not name a supported format and version.
+    UnsupportedFormat(String),
+    /// A line could not be parsed. Lines are counted from 1.
+    Malformed {
+        line: usize,
+        text: String,
+    },
+    /// A required key is missing.
+    MissingKey(&'static str),
+}
+
+impl<W: Word> Intcode<W> {
+    /// Writes a snapshot of the machine, see the module documentation for the
+    /// format. Fails with `InvalidInput` if the instruction set has custom
+    /// operations.
+    pub fn save<T: Write + ?Sized>(&self, out: &mut T) -> io::Result<()> {
+        if let Some(isa) = &self.isa {
+            if isa.has_custom_operations() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "custom operations cannot be saved in a snapshot",
+                ));
+            }
+        }
+
+        writeln!(out, "{} {}", HEADER, VERSION)?;
+        writeln!(out, "position {}", self.position)?;
+        writeln!(out, "relative-base {}", self.relative_base)?;
+        writeln!(out, "program-length {}", self.program_len)?;
+
+        let mut segment: Option<(usize, Vec<&W>)> = None;
+        for (address, value) in self.memory.cells() {
+            match &mut segment {
+                Some((start, words)) if *start + words.len() == address => words.push(value),
+                _ => {
+                    if let Some((start, words)) = segment.take() {
+                        writeln!(out, "memory {} {}", start, join(words))?;
+                    }
+                    segment = Some((address, vec![value]));
+                }
+            }
+        }
+        if let Some((start, words)) = segment {
+            writeln!(out, "memory {} {}", start, join(words))?;
+        }
+
+        if !self.inputs.is_empty() {
+            writeln!(out, "input {}", join(&self.inputs))?;
+        }
+        if !self.outputs.is_empty() {
+            writeln!(out, "output {}", join(&self.outputs))?;
+        }
+
+        if let Some(isa) = &self.isa {
+            writeln!(out, "instruction-set {}", isa.name())?;
+        }
+        if let Some(budget) = &self.budget {
+            let limits = &budget.limits;
+            if let Some(instructions) = limits.instructions {
+                writeln!(
+                    out,
+                    "instruction-limit {} {}",
+                    instructions, budget.executed
+                )?;
+            }
+            if let Some(time) = limits.time {
+                writeln!(out, "time-limit {} {}", time.as_secs(), time.subsec_nanos())?;
+            }
+            if let Some(memory) = limits.memory {
+                writeln!(out, "memory-limit {}", memory)?;
+            }
+            if let Some(outputs) = limits.outputs {
+                writeln!(out, "output-limit {} {}", outputs, budget.outputs)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn save_file(&self, filename: &str) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(filename)?);
+        self.save(&mut out)?;
+        out.flush()
+    }
+
+    /// Reads a snapshot written by `save` into a fresh machine.
+    pub fn restore<R: BufRead>(reader: R) -> Result<Intcode<W>, SnapshotError> {
+        let mut lines = reader.lines();
+
+        let header = lines.next().transpose()?.unwrap_or_default();
+        let version = match header.trim().strip_prefix(HEADER) {
+            Some(version) => version.trim().parse::<u32>().ok(),
+            None => None,
+        };
+        if !version.is_some_and(|version| (1..=VERSION).contains(&version)) {
+            return Err(SnapshotError::UnsupportedFormat(header));
+        }
+
+        let mut machine = Intcode::with_words(vec![]);
+        let mut position = None;
+        let mut relative_base = None;
+        let mut program_len = None;
+        let mut limits = Limits::default();
+        let mut executed = 0;
+        let mut outputs = 0;
+
+        for (index, line) in lines.enumerate() {
+            let line = line?;
+            let number = index + 2;
+            let malformed = || SnapshotError::Malformed {
+                line: number,
+                text: line.clone(),
+            };
+
+            let text = line.trim();
+            if text.is_empty() {
+                continue;
+            }
+
+            let (key, value) = match text.find(' ') {
+                Some(space) => (&text[..space], text[space + 1..].trim()),
+                None => (text, ""),
+            };
+
+            match key {
+                "position" => position = Some(value.parse().map_err(|_| malformed())?),
+                "relative-base" => relative_base = Some(value.parse().map_err(|_| malformed())?),
+                "program-length" => program_len = Some(value.parse().map_err(|_| malformed())?),
+                "memory" => {
+                    let mut parts = value.splitn(2, ' ');
+                    let start: usize = parts
+                        .next()
+                        .and_then(|start| start.parse().ok())
+                        .ok_or_else(malformed)?;
+                    let words: Vec<W> =
+                        parse_words(parts.next().unwrap_or("")).ok_or_else(malformed)?;
+
+                    for (offset, word) in words.into_iter().enumerate() {
+                        let address = start.checked_add(offset).ok_or_else(malformed)?;
+                        machine.memory.set(address, word);
+                    }
+                }
+                "input" => machine.inputs = parse_words(value).ok_or_else(malformed)?,
+                "output" => machine.outputs = parse_words(value).ok_or_else(malformed)?,
+                "instruction-set" => {
+                    let isa = InstructionSet::profile(value).ok_or_else(malformed)?;
+                    machine.set_instruction_set(isa);
+                }
+                "instruction-limit" => {
+                    let [limit, used] = parse_numbers(value).ok_or_else(malformed)?;
+                    limits.instructions = Some(limit);
+                    executed = used;
+                }
+                "time-limit" => {
+                    let [seconds, nanos] = parse_numbers(value).ok_or_else(malformed)?;
+                    let nanos = u32::try_from(nanos).map_err(|_| malformed())?;
+                    limits.time = Some(Duration::new(seconds, nanos));
+                }
+                "memory-limit" => limits.memory = Some(value.parse().map_err(|_| malformed())?),
+                "output-limit" => {
+                    let [limit, used] = parse_numbers(value).ok_or_else(malformed)?;
+                    limits.outputs = Some(usize::try_from(limit).map_err(|_| malformed())?);
+                    outputs = usize::try_from(used).map_err(|_| malformed())?;
+                }
+                _ => return Err(malformed()),
+            }
+        }
+
+        machine.position = position.ok_or(SnapshotError::MissingKey("position"))?;
+        machine.relative_base = relative_base.ok_or(SnapshotError::MissingKey("relative-base"))?;
+        machine.program_len = program_len.unwrap_or_else(|| machine.memory.len());
+        if limits != Limits::default() {
+            let mut budget = Budget::new(limits);
+            budget.executed = executed;
+            budget.outputs = outputs;
+            machine.budget = Some(Box::new(budget));
+        }
+        Ok(machine)
+    }
+
+    pub fn restore_file(filename: &str) -> Result<Intcode<W>, SnapshotError> {
+        Intcode::restore(BufReader::new(File::open(filename)?))
+    }
+}
+
+fn join<'a, W: Word + 'a>(words: impl IntoIterator<Item = &'a W>) -> String {
+    let words: Vec<String> = words.into_iter().map(|word| word.to_string()).collect();
+    words.join(",")
+}
+
+/// Parses a fixed number of space separated numbers.
+fn parse_numbers<const N: usize>(text: &str) -> Option<[u64; N]> {
+    let numbers: Vec<u64> = text
+        .split_whitespace()
+        .map(|number| number.parse().ok())
+        .collect::<Option<_>>()?;
+
+    numbers.try_into().ok()
+}
+
+fn parse_words<W: Word, C: std::iter::FromIterator<W>>(text: &str) -> Option<C> {
+    if text.is_empty() {
+        return Some(std::iter::empty().collect());
+    }
+
+    text.split(',')
+        .map(|word| word.trim().parse().ok())
+        .collect()
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(error: io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "Could not read snapshot: {}", error),
+            SnapshotError::UnsupportedFormat(header) => {
+                write!(f, "Unsupported snapshot format `{}`", header)
+            }
+            SnapshotError::Malformed { line, text } => {
+                write!(f, "Malformed snapshot line {}: `{}`", line, text)
+            }
+            SnapshotError::MissingKey(key) => write!(f, "Snapshot is missing `{}`", key),
+        }
+    }
+}
+
+impl Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SnapshotError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Status;
+    use super::*;
+
+    fn snapshot(machine: &Intcode) -> String {
+        let mut out = vec![];
+        machine.save(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn save_format() {
+        // Doubles its input, then waits for another one.
+        let mut machine = Intcode::new(vec![3, 9, 102, 2, 9, 9, 4, 9, 3, 0]);
+        machine.provide_input(21);
+        assert_eq!(machine.pump().unwrap(), Status::NeedsInput);
+        machine.provide_input(7);
+        machine.memory.set(1 << 20, -3);
+
+        assert_eq!(
+            snapshot(&machine),
+            "intcode-snapshot 2\n\
+             position 8\n\
+             relative-base 0\n\
+             program-length 10\n\
+             memory 0 3,9,102,2,9,9,4,9,3,42\n\
+             memory 1048576 -3\n\
+             input 7\n\
+             output 42\n"
+        );
+    }
+
+    #[test]
+    fn restore_and_resume() {
+        // Echoes inputs until it reads a zero.
+        let mut machine = Intcode::new(vec![3, 11, 4, 11, 1005, 11, 0, 99, 0, 0, 0, 0]);
+        machine.provide_input(3);
+        assert_eq!(machine.pump().unwrap(), Status::NeedsInput);
+        machine.provide_input(2);
+
+        let mut restored = Intcode::restore(snapshot(&machine).as_bytes()).unwrap();
+        assert_eq!(snapshot(&restored), snapshot(&machine));
+
+        restored.provide_input(0);
+        assert_eq!(restored.pump().unwrap(), Status::Halted);
+        assert_eq!(restored.take_outputs(), vec![3, 2, 0]);
+        assert!(restored.take_outputs().is_empty());
+    }
+
+    #[test]
+    fn relative_base_and_snapshot_files() -> Result<(), SnapshotError> {
+        let mut machine = Intcode::new(vec![109, -5, 99]);
+        machine.resume().unwrap();
+
+        // Concurrent test runs must not share the file.
+        let filename = std::env::temp_dir().join(format!(
+            "intcode-snapshot-{}-{:?}.txt",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        let filename = filename.to_str().unwrap();
+        machine.save_file(filename)?;
+        let restored: Intcode = Intcode::restore_file(filename)?;
+        std::fs::remove_file(filename)?;
+
+        assert_eq!(restored.position(), 2);
+        assert_eq!(*restored.relative_base(), -5);
+        Ok(())
+    }
+
+    #[test]
+    fn instruction_sets_and_limits() {
+        // Outputs 1 forever.
+        let mut machine = Intcode::new(vec![104, 1, 1105, 1, 0]);
+        machine.set_instruction_set(InstructionSet::day05());
+        machine.set_limits(Limits {
+            instructions: Some(100),
+            time: Some(Duration::new(2, 500)),
+            memory: Some(4096),
+            outputs: Some(3),
+        });
+        assert!(machine.run(vec![]).is_err());
+
+        let text = snapshot(&machine);
+        assert!(text.ends_with(
+            "instruction-set day05\n\
+             instruction-limit 100 6\n\
+             time-limit 2 500\n\
+             memory-limit 4096\n\
+             output-limit 3 3\n"
+        ));
+
+        let restored = Intcode::restore(text.as_bytes()).unwrap();
+        assert_eq!(snapshot(&restored), text);
+        assert_eq!(restored.instruction_set().unwrap().name(), "day05");
+        assert_eq!(restored.limits(), machine.limits());
+    }
+
+    #[test]
+    fn custom_operations_are_not_saved() {
+        let mut isa = InstructionSet::full();
+        isa.register(42, &[], |_| super::super::isa::Action::Continue)
+            .unwrap();
+        let mut machine = Intcode::new(vec![42, 99]);
+        machine.set_instruction_set(isa);
+
+        let error = machine.save(&mut vec![]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn version_1() {
+        let machine: Intcode = Intcode::restore(
+            "intcode-snapshot 1\nposition 2\nrelative-base 0\nmemory 0 99\n".as_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(machine.position(), 2);
+        assert!(machine.instruction_set().is_none());
+        assert!(machine.limits().is_none());
+    }
+
+    #[test]
+    fn invalid_snapshots() {
+        let error = |text: &str| Intcode::<i64>::restore(text.as_bytes()).err().unwrap();
+
+        assert!(matches!(
+            error("intcode-snapshot 3\nposition 0\n"),
+            SnapshotError::UnsupportedFormat(_)
+        ));
+        assert!(matches!(
+            error("intcode-snapshot 1\nposition 0\nmemory 0 1,x\n"),
+            SnapshotError::Malformed { line: 3, .. }
+        ));
+        assert!(matches!(
+            error("intcode-snapshot 1\nposition 0\nregisters 1\n"),
+            SnapshotError::Malformed { line: 3, .. }
+        ));
+        assert!(matches!(
+            error("intcode-snapshot 1\nposition 0\n"),
+            SnapshotError::MissingKey("relative-base")
+        ));
+        assert!(matches!(
+            error("intcode-snapshot 2\nposition 0\ninstruction-set day07\n"),
+            SnapshotError::Malformed { line: 3, .. }
+        ));
+        assert!(matches!(
+            error("intcode-snapshot 2\nposition 0\noutput-limit 3\n"),
+            SnapshotError::Malformed { line: 3, .. }
+        ));
+    }
+}
@@ -0,0 +1,614 @@
+//! Symbolic execution of Intcode programs.
+//!
+//! Memory cells and inputs can be marked as symbols. Arithmetic and
+//! comparisons on symbols build expression trees instead of numbers, so every
+//! output and memory cell of a path through the program is a formula over the
+//! symbols. A jump on a symbolic condition either forks the path, recording
+//! the condition on both sides, or ends it with a report.
+
+use super::{Intcode, Memory, Operation};
+use std::collections::{BTreeMap, VecDeque};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// A value computed by a program from its symbols.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Const(i64),
+    Symbol(String),
+    Add(Box<Expr>, Box<Expr>),
+    Multiply(Box<Expr>, Box<Expr>),
+    /// 1 if the left side is less than the right side, otherwise 0.
+    LessThan(Box<Expr>, Box<Expr>),
+    /// 1 if both sides are equal, otherwise 0.
+    Equals(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    pub fn symbol(name: &str) -> Expr {
+        Expr::Symbol(name.to_string())
+    }
+
+    /// The value of the expression, if it does not depend on any symbol.
+    pub fn as_const(&self) -> Option<i64> {
+        match self {
+            Expr::Const(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Builds `a + b`, folding constants. Returns `None` if the sum of two
+    /// constants overflows.
+    pub fn sum(a: Expr, b: Expr) -> Option<Expr> {
+        let sum = match (a, b) {
+            (Expr::Const(a), Expr::Const(b)) => Expr::Const(a.checked_add(b)?),
+            (Expr::Const(0), x) | (x, Expr::Const(0)) => x,
+            (a, b) => Expr::Add(Box::new(a), Box::new(b)),
+        };
+
+        Some(sum)
+    }
+
+    /// Builds `a * b`, folding constants. Returns `None` if the product of two
+    /// constants overflows.
+    pub fn product(a: Expr, b: Expr) -> Option<Expr> {
+        let product = match (a, b) {
+            (Expr::Const(a), Expr::Const(b)) => Expr::Const(a.checked_mul(b)?),
+            (Expr::Const(0), _) | (_, Expr::Const(0)) => Expr::Const(0),
+            (Expr::Const(1), x) | (x, Expr::Const(1)) => x,
+            (a, b) => Expr::Multiply(Box::new(a), Box::new(b)),
+        };
+
+        Some(product)
+    }
+
+    /// Builds `a < b`, folding constants.
+    pub fn less_than(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Expr::Const(a), Expr::Const(b)) => Expr::Const((a < b) as i64),
+            (a, b) => Expr::LessThan(Box::new(a), Box::new(b)),
+        }
+    }
+
+    /// Builds `a == b`, folding constants and comparisons of an expression
+    /// with itself.
+    pub fn equals(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Expr::Const(a), Expr::Const(b)) => Expr::Const((a == b) as i64),
+            (a, b) if a == b => Expr::Const(1),
+            (a, b) => Expr::Equals(Box::new(a), Box::new(b)),
+        }
+    }
+}
+
+/// An assumption a path makes about a symbolic branch condition.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Constraint {
+    pub condition: Expr,
+    /// Whether the condition is assumed to be non-zero.
+    pub holds: bool,
+}
+
+/// Why a path ended.
+#[derive(Clone, Debug, PartialEq)]
+pub enum End {
+    Halted,
+    /// The program wants another input, but all inputs were used up.
+    NeedsInput,
+    /// A jump depends on a symbol and forking is disabled.
+    SymbolicBranch {
+        position: usize,
+        condition: Expr,
+    },
+    /// An address, jump target, relative base adjustment or opcode depends on
+    /// a symbol.
+    SymbolicAddress {
+        position: usize,
+        expr: Expr,
+    },
+    /// The program executed an invalid instruction or accessed an invalid
+    /// address.
+    Fault {
+        position: usize,
+    },
+    /// The result of an arithmetic instruction, an address or the relative
+    /// base does not fit into a word, which the machine reports as
+    /// `IntcodeError::Overflow`.
+    Overflow {
+        position: usize,
+    },
+    /// The path executed `max_steps` instructions.
+    StepLimit,
+}
+
+/// One path through the program.
+#[derive(Clone, Debug)]
+pub struct Path {
+    /// The assumptions about branch conditions that lead down this path.
+    pub constraints: Vec<Constraint>,
+    pub outputs: Vec<Expr>,
+    pub end: End,
+    /// The position of the instruction the path ended on.
+    pub position: usize,
+    /// Whether the path skipped the other side of a fork, because
+    /// `max_paths` paths were started already. The paths are only complete
+    /// if none of them is truncated.
+    pub truncated: bool,
+    memory: SymbolicMemory,
+}
+
+impl Path {
+    /// The contents of a memory cell at the end of the path.
+    pub fn memory(&self, address: usize) -> Expr {
+        self.memory.get(address)
+    }
+
+    /// The memory cells that hold symbolic values or were written on the
+    /// path, by address.
+    pub fn changed_cells(&self) -> &BTreeMap<usize, Expr> {
+        &self.memory.cells
+    }
+}
+
+pub struct SymbolicConfig {
+    /// Whether a jump on a symbolic condition forks the path. If not, the path
+    /// ends with `End::SymbolicBranch`.
+    pub fork: bool,
+    /// The maximum number of instructions executed on a single path.
+    pub max_steps: usize,
+    /// The maximum number of paths explored. Forks beyond this are dropped,
+    /// and the paths that drop them are marked as truncated.
+    pub max_paths: usize,
+}
+
+impl Default for SymbolicConfig {
+    fn default() -> Self {
+        SymbolicConfig {
+            fork: true,
+            max_steps: 100_000,
+            max_paths: 64,
+        }
+    }
+}
+
+/// The concrete memory of the program with symbolic cells on top.
+#[derive(Clone, Debug)]
+struct SymbolicMemory {
+    base: Memory<i64>,
+    cells: BTreeMap<usize, Expr>,
+}
+
+impl SymbolicMemory {
+    fn get(&self, address: usize) -> Expr {
+        match self.cells.get(&address) {
+            Some(expr) => expr.clone(),
+            None => Expr::Const(self.base.get(address)),
+        }
+    }
+
+    fn set(&mut self, address: usize, value: Expr) {
+        self.cells.insert(address, value);
+    }
+}
+
+#[derive(Clone)]
+struct State {
+    position: usize,
+    relative_base: i64,
+    memory: SymbolicMemory,
+    inputs: VecDeque<Expr>,
+    outputs: Vec<Expr>,
+    constraints: Vec<Constraint>,
+    steps: usize,
+    truncated: bool,
+}
+
+/// What executing a single instruction did to a path.
+enum Step {
+    Continue,
+    Fork { condition: Expr, taken: usize },
+    End(End),
+}
+
+/// Explores the paths of a program, starting from the current state of the
+/// machine.
+///
+/// `cells` replaces memory cells with symbols or other expressions before the
+/// program runs, and `inputs` is read after the inputs already queued on the
+/// machine. Paths are returned in the order they ended.
+pub fn explore(
+    machine: &Intcode,
+    cells: &[(usize, Expr)],
+    inputs: Vec<Expr>,
+    config: &SymbolicConfig,
+) -> Vec<Path> {
+    let mut memory = SymbolicMemory {
+        base: machine.memory.clone(),
+        cells: BTreeMap::new(),
+    };
+    for (address, expr) in cells {
+        memory.set(*address, expr.clone());
+    }
+
+    let initial = State {
+        position: machine.position,
+        relative_base: machine.relative_base,
+        memory,
+        inputs: machine
+            .inputs
+            .iter()
+            .map(|input| Expr::Const(*input))
+            .chain(inputs)
+            .collect(),
+        outputs: vec![],
+        constraints: vec![],
+        steps: 0,
+        truncated: false,
+    };
+
+    let mut pending = vec![initial];
+    let mut started = 1;
+    let mut paths = vec![];
+
+    while let Some(mut state) = pending.pop() {
+        let end = loop {
+            if state.steps >= config.max_steps {
+                break End::StepLimit;
+            }
+            state.steps += 1;
+
+            match state.step() {
+                Step::Continue => {}
+                Step::Fork { condition, .. } if !config.fork => {
+                    break End::SymbolicBranch {
+                        position: state.position,
+                        condition,
+                    };
+                }
+                Step::Fork { condition, taken } => {
+                    if started < config.max_paths {
+                        let mut other = state.clone();
+                        other.assume(condition.clone(), false);
+                        other.position += 3;
+                        pending.push(other);
+                        started += 1;
+                    } else {
+                        state.truncated = true;
+                    }
+
+                    state.assume(condition, true);
+                    state.position = taken;
+                }
+                Step::End(end) => break end,
+            }
+        };
+
+        paths.push(Path {
+            constraints: state.constraints,
+            outputs: state.outputs,
+            end,
+            position: state.position,
+            truncated: state.truncated,
+            memory: state.memory,
+        });
+    }
+
+    paths
+}
+
+impl State {
+    fn assume(&mut self, condition: Expr, holds: bool) {
+        self.constraints.push(Constraint { condition, holds });
+    }
+
+    /// Executes the instruction at the current position. The position is only
+    /// advanced if the path continues.
+    fn step(&mut self) -> Step {
+        let start = self.position;
+        let fault = End::Fault { position: start };
+
+        let opcode = match self.memory.get(start) {
+            Expr::Const(opcode) => opcode,
+            expr => {
+                return Step::End(End::SymbolicAddress {
+                    position: start,
+                    expr,
+                })
+            }
+        };
+        let operation = match Operation::from_opcode(opcode % 100) {
+            Some(operation) => operation,
+            None => return Step::End(fault),
+        };
+
+        let mut parameters = vec![];
+        let mut modes = opcode / 100;
+        for offset in 1..=operation.arity() {
+            parameters.push((modes % 10, self.memory.get(start + offset)));
+            modes /= 10;
+        }
+
+        match self.execute(start, operation, &parameters) {
+            Ok(step) => step,
+            Err(end) => Step::End(end),
+        }
+    }
+
+    fn execute(
+        &mut self,
+        start: usize,
+        operation: Operation,
+        parameters: &[(i64, Expr)],
+    ) -> Result<Step, End> {
+        let next = start + 1 + parameters.len();
+        let overflow = End::Overflow { position: start };
+        let value = |index: usize| self.value(start, &parameters[index]);
+        let target = |index: usize| self.address(start, &parameters[index]);
+
+        match operation {
+            Operation::Add => {
+                let result = Expr::sum(value(0)?, value(1)?).ok_or(overflow)?;
+                self.memory.set(target(2)?, result);
+            }
+            Operation::Multiply => {
+                let result = Expr::product(value(0)?, value(1)?).ok_or(overflow)?;
+                self.memory.set(target(2)?, result);
+            }
+            Operation::LessThan => {
+                let result = Expr::less_than(value(0)?, value(1)?);
+                self.memory.set(target(2)?, result);
+            }
+            Operation::Equals => {
+                let result = Expr::equals(value(0)?, value(1)?);
+                self.memory.set(target(2)?, result);
+            }
+            Operation::Input => {
+                let target = target(0)?;
+                match self.inputs.pop_front() {
+                    Some(input) => self.memory.set(target, input),
+                    None => return Err(End::NeedsInput),
+                }
+            }
+            Operation::Output => {
+                let output = value(0)?;
+                self.outputs.push(output);
+            }
+            Operation::JumpIfTrue | Operation::JumpIfFalse => {
+                // Like the machine, the destination is only read once the
+                // jump may be taken.
+                let condition = value(0)?;
+                let destination = || {
+                    let destination = self.concrete(start, value(1)?)?;
+                    usize::try_from(destination).map_err(|_| End::Fault { position: start })
+                };
+
+                match condition.as_const() {
+                    Some(condition) => {
+                        let jump = (condition != 0) == (operation == Operation::JumpIfTrue);
+                        self.position = if jump { destination()? } else { next };
+                        return Ok(Step::Continue);
+                    }
+                    None if operation == Operation::JumpIfTrue => {
+                        let taken = destination()?;
+                        return Ok(Step::Fork { condition, taken });
+                    }
+                    // Jumping if false is the same as jumping if `c == 0`.
+                    None => {
+                        return Ok(Step::Fork {
+                            condition: Expr::equals(condition, Expr::Const(0)),
+                            taken: destination()?,
+                        });
+                    }
+                }
+            }
+            Operation::AdjustRelativeBase => {
+                let offset = self.concrete(start, value(0)?)?;
+                self.relative_base = self.relative_base.checked_add(offset).ok_or(overflow)?;
+            }
+            Operation::Halt => return Err(End::Halted),
+            // Opcodes are decoded without an instruction set, so this is
+            // never reached.
+            Operation::Custom { .. } => return Err(End::Fault { position: start }),
+        }
+
+        self.position = next;
+        Ok(Step::Continue)
+    }
+
+    fn concrete(&self, position: usize, expr: Expr) -> Result<i64, End> {
+        expr.as_const()
+            .ok_or(End::SymbolicAddress { position, expr })
+    }
+
+    fn address(&self, position: usize, (mode, raw): &(i64, Expr)) -> Result<usize, End> {
+        let raw = self.concrete(position, raw.clone())?;
+        let address = match mode {
+            0 => raw,
+            2 => self
+                .relative_base
+                .checked_add(raw)
+                .ok_or(End::Overflow { position })?,
+            _ => return Err(End::Fault { position }),
+        };
+
+        usize::try_from(address).map_err(|_| End::Fault { position })
+    }
+
+    fn value(&self, position: usize, parameter: &(i64, Expr)) -> Result<Expr, End> {
+        match parameter {
+            (1, value) => Ok(value.clone()),
+            _ => Ok(self.memory.get(self.address(position, parameter)?)),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Const(value) => write!(f, "{}", value),
+            Expr::Symbol(name) => write!(f, "{}", name),
+            Expr::Add(a, b) => write!(f, "({} + {})", a, b),
+            Expr::Multiply(a, b) => write!(f, "({} * {})", a, b),
+            Expr::LessThan(a, b) => write!(f, "({} < {})", a, b),
+            Expr::Equals(a, b) => write!(f, "({} == {})", a, b),
+        }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let relation = if self.holds { "!=" } else { "==" };
+        write!(f, "{} {} 0", self.condition, relation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formula_for_patched_cells() {
+        // Computes `[0] = 3 * (noun + verb)`.
+        let program = Intcode::new(vec![1101, 0, 0, 0, 1002, 0, 3, 0, 99]);
+        let cells = [(1, Expr::symbol("noun")), (2, Expr::symbol("verb"))];
+        let paths = explore(&program, &cells, vec![], &SymbolicConfig::default());
+
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].end, End::Halted);
+        assert_eq!(paths[0].memory(0).to_string(), "((noun + verb) * 3)");
+        assert_eq!(paths[0].memory(4), Expr::Const(1002));
+    }
+
+    #[test]
+    fn fork_on_symbolic_input() {
+        // Outputs 1 if the input equals 8, otherwise 0.
+        let program = Intcode::new(vec![3, 12, 8, 12, 13, 12, 1005, 12, 11, 104, 0, 99, 0, 8]);
+        let input = vec![Expr::symbol("x")];
+        let paths = explore(&program, &[], input.clone(), &SymbolicConfig::default());
+
+        let summary: Vec<(String, Vec<i64>)> = paths
+            .iter()
+            .map(|path| {
+                let outputs = path.outputs.iter().filter_map(Expr::as_const).collect();
+                (path.constraints[0].to_string(), outputs)
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("(x == 8) != 0".to_string(), vec![]),
+                ("(x == 8) == 0".to_string(), vec![0]),
+            ]
+        );
+
+        assert!(paths.iter().all(|path| !path.truncated));
+
+        let config = SymbolicConfig {
+            max_paths: 1,
+            ..SymbolicConfig::default()
+        };
+        let paths = explore(&program, &[], input.clone(), &config);
+        assert_eq!(paths.len(), 1);
+        assert!(paths[0].truncated);
+
+        let config = SymbolicConfig {
+            fork: false,
+            ..SymbolicConfig::default()
+        };
+        let paths = explore(&program, &[], input, &config);
+        assert_eq!(
+            paths[0].end,
+            End::SymbolicBranch {
+                position: 6,
+                condition: Expr::equals(Expr::symbol("x"), Expr::Const(8))
+            }
+        );
+    }
+
+    #[test]
+    fn diagnostic_formulas() -> Result<(), super::super::IntcodeError> {
+        let mut program = Intcode::from_file("inputs/day05.txt")?;
+
+        // The system id is added to an opcode, so it cannot be a symbol.
+        let paths = explore(
+            &program,
+            &[],
+            vec![Expr::symbol("id")],
+            &SymbolicConfig::default(),
+        );
+        assert_eq!(
+            paths[0].end,
+            End::SymbolicAddress {
+                position: 6,
+                expr: Expr::sum(Expr::symbol("id"), Expr::Const(1100)).unwrap()
+            }
+        );
+
+        program.provide_input(1);
+        let config = SymbolicConfig {
+            fork: false,
+            ..SymbolicConfig::default()
+        };
+        let paths = explore(&program, &[(92, Expr::symbol("m92"))], vec![], &config);
+
+        // The first test reports zero because the word at 92 is 38, and the
+        // final diagnostic code folds in every test result.
+        assert_eq!(paths[0].end, End::Halted);
+        assert_eq!(paths[0].outputs[1].to_string(), "(-83 + (m92 + 45))");
+        assert!(paths[0].outputs[9].to_string().contains("m92"));
+        Ok(())
+    }
+
+    #[test]
+    fn overflow_like_the_machine() {
+        let programs = vec![
+            (vec![1102, i64::MAX, 2, 0, 99], 0),
+            (vec![109, i64::MAX, 109, 1, 99], 2),
+            (vec![109, i64::MAX, 204, 1, 99], 2),
+        ];
+
+        for (program, position) in programs {
+            let program = Intcode::new(program);
+            let paths = explore(&program, &[], vec![], &SymbolicConfig::default());
+
+            assert_eq!(paths[0].end, End::Overflow { position });
+            assert!(matches!(
+                program.clone().run(vec![]),
+                Err(super::super::IntcodeError::Overflow { position: p, .. }) if p == position
+            ));
+        }
+    }
+
+    #[test]
+    fn jumps_not_taken() {
+        // Skips a jump to an invalid address, then to a symbolic one.
+        let program = Intcode::new(vec![1105, 0, -1, 1006, 9, 0, 104, 7, 99, 1]);
+        let cells = [(5, Expr::symbol("to"))];
+        let paths = explore(&program, &cells, vec![], &SymbolicConfig::default());
+
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].end, End::Halted);
+        assert_eq!(paths[0].outputs, vec![Expr::Const(7)]);
+        assert_eq!(program.clone().run(vec![]).unwrap(), vec![7]);
+
+        let program = Intcode::new(vec![1105, 0, -1, 104, 7, 99]);
+        let paths = explore(&program, &[], vec![], &SymbolicConfig::default());
+        assert_eq!(paths[0].end, End::Halted);
+        assert_eq!(paths[0].outputs, vec![Expr::Const(7)]);
+    }
+
+    #[test]
+    fn symbolic_addresses() {
+        let program = Intcode::new(vec![1005, 0, 99]);
+        let cells = [(0, Expr::symbol("op"))];
+        let paths = explore(&program, &cells, vec![], &SymbolicConfig::default());
+
+        assert_eq!(
+            paths[0].end,
+            End::SymbolicAddress {
+                position: 0,
+                expr: Expr::symbol("op")
+            }
+        );
+    }
+}
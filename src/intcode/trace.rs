@@ -0,0 +1,265 @@
+use super::{Instruction, Status, Word};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Write};
+
+/// A parameter after its addressing mode was applied.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operand<W = i64> {
+    /// The value a parameter reads.
+    Value(W),
+    /// The address a parameter writes to.
+    Address(usize),
+}
+
+/// A memory cell an instruction wrote to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryWrite<W = i64> {
+    pub address: usize,
+    pub old: W,
+    pub new: W,
+}
+
+/// An instruction about to be executed.
+pub struct Event<'a, W = i64> {
+    /// The address of the instruction.
+    pub position: usize,
+    pub instruction: &'a Instruction<W>,
+    /// The resolved parameters, in the order of `instruction.parameters`.
+    pub operands: &'a [Operand<W>],
+    pub relative_base: &'a W,
+}
+
+/// What an instruction did once it was executed.
+pub struct Effects<'a, W = i64> {
+    pub writes: &'a [MemoryWrite<W>],
+    /// The instruction pointer after the instruction.
+    pub position: usize,
+    pub status: &'a Status<W>,
+}
+
+/// Observes every instruction a machine executes, see `Intcode::step_traced`.
+///
+/// Instructions that fail to decode or resolve their parameters are not seen
+/// by the tracer, and instructions that fail while executing are only seen by
+/// `before`. The error returned from the step describes them.
+pub trait Tracer<W = i64> {
+    /// Whether the tracer wants to see instructions at all. Machines skip the
+    /// bookkeeping for tracers that are not enabled.
+    fn is_enabled(&self) -> bool {
+        true
+    }
+
+    fn before(&mut self, _event: &Event<W>) {}
+
+    fn after(&mut self, _event: &Event<W>, _effects: &Effects<W>) {}
+}
+
+impl<W> Tracer<W> for () {
+    fn is_enabled(&self) -> bool {
+        false
+    }
+}
+
+/// An owned copy of a traced instruction and its effects.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record<W = i64> {
+    pub position: usize,
+    pub instruction: Instruction<W>,
+    pub operands: Vec<Operand<W>>,
+    pub writes: Vec<MemoryWrite<W>>,
+    pub next_position: usize,
+}
+
+impl<W: Clone> Record<W> {
+    pub fn new(event: &Event<W>, effects: &Effects<W>) -> Record<W> {
+        Record {
+            position: event.position,
+            instruction: event.instruction.clone(),
+            operands: event.operands.to_vec(),
+            writes: effects.writes.to_vec(),
+            next_position: effects.position,
+        }
+    }
+}
+
+/// Writes one line per executed instruction, e.g.
+/// `   12: add [4], #3, [4] ; 5, 3, @4 ; [4] 5 -> 8 ; ip 16`.
+pub struct TraceWriter<T> {
+    out: T,
+    error: Option<io::Error>,
+}
+
+impl<T: Write> TraceWriter<T> {
+    pub fn new(out: T) -> TraceWriter<T> {
+        TraceWriter { out, error: None }
+    }
+
+    /// Flushes the trace and returns the writer, or the first error that
+    /// occurred while tracing. Tracing stops after the first error.
+    pub fn finish(mut self) -> io::Result<T> {
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => {
+                self.out.flush()?;
+                Ok(self.out)
+            }
+        }
+    }
+}
+
+impl<W: Word, T: Write> Tracer<W> for TraceWriter<T> {
+    fn after(&mut self, event: &Event<W>, effects: &Effects<W>) {
+        if self.error.is_none() {
+            let record = Record::new(event, effects);
+            if let Err(error) = writeln!(self.out, "{}", record) {
+                self.error = Some(error);
+            }
+        }
+    }
+}
+
+/// Keeps the last instructions a machine executed.
+pub struct RingTracer<W = i64> {
+    capacity: usize,
+    records: VecDeque<Record<W>>,
+}
+
+impl<W: Word> RingTracer<W> {
+    pub fn new(capacity: usize) -> RingTracer<W> {
+        RingTracer {
+            capacity,
+            records: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// The kept instructions, oldest first.
+    pub fn records(&self) -> impl Iterator<Item = &Record<W>> {
+        self.records.iter()
+    }
+
+    /// The kept instructions in the format of `TraceWriter`.
+    pub fn history(&self) -> String {
+        self.records
+            .iter()
+            .map(|record| format!("{}\n", record))
+            .collect()
+    }
+}
+
+impl<W: Word> Tracer<W> for RingTracer<W> {
+    fn after(&mut self, event: &Event<W>, effects: &Effects<W>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(Record::new(event, effects));
+    }
+}
+
+impl<W: Word> fmt::Display for Operand<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Value(value) => write!(f, "{}", value),
+            Operand::Address(address) => write!(f, "@{}", address),
+        }
+    }
+}
+
+impl<W: Word> fmt::Display for Record<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:>5}: {}", self.position, self.instruction)?;
+
+        if !self.operands.is_empty() {
+            let operands: Vec<String> = self.operands.iter().map(|op| op.to_string()).collect();
+            write!(f, " ; {}", operands.join(", "))?;
+        }
+        for write in &self.writes {
+            write!(f, " ; [{}] {} -> {}", write.address, write.old, write.new)?;
+        }
+
+        write!(f, " ; ip {}", self.next_position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Intcode;
+    use super::*;
+
+    #[test]
+    fn trace_file_lines() {
+        let mut program = Intcode::new(vec![1002, 6, 3, 6, 4, 6, 33, 99]);
+        let mut tracer = TraceWriter::new(vec![]);
+
+        while program.step_traced(&mut tracer).unwrap() != Status::Halted {}
+
+        let trace = String::from_utf8(tracer.finish().unwrap()).unwrap();
+        assert_eq!(
+            trace,
+            "    0: mul [6], #3, [6] ; 33, 3, @6 ; [6] 33 -> 99 ; ip 4\n\
+             \x20   4: out [6] ; 99 ; ip 6\n\
+             \x20   6: hlt ; ip 6\n"
+        );
+    }
+
+    #[test]
+    fn ring_keeps_last_instructions() {
+        // Counts down from 5 and fails on the unknown opcode at the end.
+        let mut program = Intcode::new(vec![1001, 9, -1, 9, 1005, 9, 0, 42, 0, 5]);
+        let mut tracer = RingTracer::new(3);
+
+        let error = loop {
+            match program.step_traced(&mut tracer) {
+                Ok(_) => continue,
+                Err(error) => break error,
+            }
+        };
+
+        assert_eq!(error.position(), Some(7));
+        let positions: Vec<usize> = tracer.records().map(|record| record.position).collect();
+        assert_eq!(positions, vec![4, 0, 4]);
+        assert_eq!(
+            tracer.history().lines().last(),
+            Some("    4: jt [9], #0 ; 0, 0 ; ip 7")
+        );
+    }
+
+    #[test]
+    fn before_and_after_hooks() {
+        #[derive(Default)]
+        struct Hooks {
+            before: Vec<usize>,
+            after: Vec<(usize, bool)>,
+        }
+
+        impl Tracer for Hooks {
+            fn before(&mut self, event: &Event) {
+                self.before.push(event.position);
+            }
+
+            fn after(&mut self, event: &Event, effects: &Effects) {
+                let waiting = *effects.status == Status::NeedsInput;
+                self.after.push((event.position, waiting));
+            }
+        }
+
+        let mut program = Intcode::new(vec![3, 5, 4, 5, 99, 0]);
+        let mut hooks = Hooks::default();
+
+        assert_eq!(
+            program.resume_traced(&mut hooks).unwrap(),
+            Status::NeedsInput
+        );
+        program.provide_input(6);
+        assert_eq!(
+            program.resume_traced(&mut hooks).unwrap(),
+            Status::Output(6)
+        );
+
+        assert_eq!(hooks.before, vec![0, 0, 2]);
+        assert_eq!(hooks.after, vec![(0, true), (0, false), (2, false)]);
+    }
+}
@@ -0,0 +1,93 @@
+use std::fmt::{Debug, Display};
+use std::str::FromStr;
+
+/// A memory cell of the Intcode machine.
+///
+/// The machine only needs a handful of operations from its words, so it can
+/// run on any integer type: `i64` is the default, `i32` and `i128` are
+/// available, and an arbitrary precision `BigInt` is provided with the
+/// `bigint` feature.
+pub trait Word: Clone + PartialEq + PartialOrd + Debug + Display + FromStr {
+    fn zero() -> Self;
+    fn one() -> Self;
+
+    fn is_zero(&self) -> bool {
+        *self == Self::zero()
+    }
+
+    /// Converts the word into an `i64`, if it fits.
+    fn to_i64(&self) -> Option<i64>;
+
+    /// Converts the word into a memory address, if it is a valid one.
+    fn to_address(&self) -> Option<usize>;
+
+    /// Adds two words, returning `None` on overflow.
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+
+    /// Multiplies two words, returning `None` on overflow.
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+}
+
+macro_rules! primitive_word {
+    ($($t:ty),*) => {
+        $(
+            impl Word for $t {
+                fn zero() -> Self {
+                    0
+                }
+
+                fn one() -> Self {
+                    1
+                }
+
+                #[allow(clippy::unnecessary_fallible_conversions)]
+                fn to_i64(&self) -> Option<i64> {
+                    use std::convert::TryFrom;
+                    i64::try_from(*self).ok()
+                }
+
+                fn to_address(&self) -> Option<usize> {
+                    use std::convert::TryFrom;
+                    usize::try_from(*self).ok()
+                }
+
+                fn checked_add(&self, other: &Self) -> Option<Self> {
+                    <$t>::checked_add(*self, *other)
+                }
+
+                fn checked_mul(&self, other: &Self) -> Option<Self> {
+                    <$t>::checked_mul(*self, *other)
+                }
+            }
+        )*
+    };
+}
+
+primitive_word!(i32, i64, i128);
+
+#[cfg(feature = "bigint")]
+impl Word for num_bigint::BigInt {
+    fn zero() -> Self {
+        num_traits::Zero::zero()
+    }
+
+    fn one() -> Self {
+        num_traits::One::one()
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        num_traits::ToPrimitive::to_i64(self)
+    }
+
+    fn to_address(&self) -> Option<usize> {
+        num_traits::ToPrimitive::to_usize(self)
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+}